@@ -16,7 +16,7 @@ fn do_another_thing() -> Result<()> {
 // Chain the external error using std::error::Error features
 // https://doc.rust-lang.org/rust-by-example/error/multiple_error_types/boxing_errors.html
 fn do_external_thing() -> Result<(), Box<dyn std::error::Error>> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, "oh no!"))?
+    Err(std::io::Error::other("oh no!"))?
 }
 
 fn main() {
@@ -34,7 +34,7 @@ fn do_external_thing() -> std::result::Result<(), SuperError> {
 fn main() {
     if let Err(err) = do_something() {
         // Traverse the error chain
-        let mut source = Some(err.as_ref());
+        let mut source = Some(err.std());
         while let Some(err) = source {
             match_err!(err, {
                 // Using alternate form of display for `Error` to get just the message
@@ -12,7 +12,7 @@ fn do_another_thing() -> Result<()> {
 
 // Create an external error to wrap
 fn do_final_thing() -> std::io::Result<()> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, "Oh no, we missed!"))
+    Err(std::io::Error::other("Oh no, we missed!"))
 }
 
 fn main() {
@@ -5,7 +5,7 @@ fn do_something() -> Result<()> {
 }
 
 fn do_external_thing() -> std::io::Result<()> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, "Oh no, we missed!"))?
+    Err(std::io::Error::other("Oh no, we missed!"))?
 }
 
 fn main() {
@@ -11,7 +11,7 @@ fn retry_on_concreate_error_type_using_err_is() -> Result<()> {
     result.wrap("Failed while attacking beast")
 }
 fn do_external_thing() -> std::io::Result<()> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, "Oh no, we missed!"))
+    Err(std::io::Error::other("Oh no, we missed!"))
 }
 
 fn main() {
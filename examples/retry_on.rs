@@ -9,7 +9,7 @@ fn retry_on_concreate_error_type() -> Result<()> {
         .wrap("Failed while attacking beast")
 }
 fn do_external_thing() -> std::io::Result<()> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, "Oh no, we missed!"))
+    Err(std::io::Error::other("Oh no, we missed!"))
 }
 
 fn main() {
@@ -1,4 +1,8 @@
-use std::{fmt::Write, path::Path};
+use std::{
+    fmt::Write,
+    path::Path,
+    sync::{OnceLock, RwLock},
+};
 
 const DEPENDENCY_FILE_PREFIXES: &[&str] = &[
     "/rustc/",
@@ -33,11 +37,133 @@ const DEPENDENCY_SYM_PREFIXES: &[&str] = &[
 
 const DEPENDENCY_SYM_CONTAINS: &[&str] = &["as witcher::wrapper::Wrapper"];
 
-// Process the given backtrace return a simplified Frame collection
-pub(crate) fn new() -> Vec<Frame> {
-    let bt = backtrace::Backtrace::new();
+// Custom dependency filters registered in addition to the built-ins
+static FILTER_PREFIXES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+static FILTER_CONTAINS: RwLock<Vec<String>> = RwLock::new(Vec::new());
 
-    bt.frames()
+/// Register a custom prefix identifying dependency frames to filter out of the backtrace.
+///
+/// Frames with a symbol or filename starting with the given prefix will be treated as
+/// dependencies in addition to the built-in filters. Call this once at startup.
+///
+/// ### Examples
+/// ```
+/// witcher::add_filter_prefix("my_generated::");
+/// ```
+pub fn add_filter_prefix(prefix: &str) {
+    FILTER_PREFIXES.write().unwrap().push(prefix.to_string());
+}
+
+/// Register a custom pattern identifying dependency frames to filter out of the backtrace.
+///
+/// Frames with a symbol or filename containing the given pattern will be treated as
+/// dependencies in addition to the built-in filters. Call this once at startup.
+///
+/// ### Examples
+/// ```
+/// witcher::add_filter_contains("/target/debug/build/");
+/// ```
+pub fn add_filter_contains(pattern: &str) {
+    FILTER_CONTAINS.write().unwrap().push(pattern.to_string());
+}
+
+/// Environment variable limiting the number of raw frames captured for a backtrace.
+///
+/// Capturing a full backtrace is expensive and often the top frames suffice. When set to
+/// a number `N` capture will stop after `N` raw frames. Unset means unlimited.
+pub const WITCHER_FRAME_LIMIT: &str = "WITCHER_FRAME_LIMIT";
+
+/// Environment variable enabling retention of the original `backtrace::Backtrace`.
+///
+/// When set to a truthy value errors keep the raw backtrace available via `raw_backtrace`
+/// in addition to the simplified frames. Disabled by default to avoid the memory cost.
+pub const WITCHER_RAW_BACKTRACE: &str = "WITCHER_RAW_BACKTRACE";
+
+/// Environment variable controlling backtrace capture independently of `RUST_BACKTRACE`.
+///
+/// When set a truthy value enables capture and `0` or `false` disables it regardless of
+/// `RUST_BACKTRACE`. When unset capture is enabled unless `RUST_BACKTRACE` is set to `0`.
+pub const WITCHER_BACKTRACE: &str = "WITCHER_BACKTRACE";
+
+// Check if backtrace capture is enabled with `WITCHER_BACKTRACE` taking precedence
+pub(crate) fn enabled() -> bool {
+    match std::env::var_os(WITCHER_BACKTRACE) {
+        Some(_) => crate::error::flag(WITCHER_BACKTRACE),
+        None => std::env::var("RUST_BACKTRACE").map_or(true, |x| x != "0"),
+    }
+}
+
+// Stack captured for an error without symbols which are only resolved the first time the
+// frames are needed as symbolication is by far the most expensive part of a backtrace
+#[derive(Clone, Default)]
+pub(crate) struct Stack {
+    unresolved: Option<backtrace::Backtrace>,
+    retain: bool,
+    resolved: OnceLock<Resolved>,
+}
+
+// Simplified frames and the original backtrace if retained once symbols are resolved
+#[derive(Clone, Default)]
+struct Resolved {
+    frames: Vec<Frame>,
+    raw: Option<backtrace::Backtrace>,
+}
+
+impl Stack {
+    // Capture the stack once stopping after the frame limit if set.
+    // Nothing is captured when backtraces are disabled to keep error construction cheap.
+    pub(crate) fn capture() -> Self {
+        if !enabled() {
+            return Stack::default();
+        }
+        let unresolved: backtrace::Backtrace = capture(frame_limit(), |f| backtrace::trace(|frame| f(frame.clone().into()))).into();
+        Stack { unresolved: Some(unresolved), retain: crate::error::flag(WITCHER_RAW_BACKTRACE), resolved: OnceLock::new() }
+    }
+
+    // Simplified frames of the stack resolving symbols on first use
+    pub(crate) fn frames(&self) -> &[Frame] {
+        &self.resolve().frames
+    }
+
+    // Simplified frames of the stack for rewriting them in place
+    pub(crate) fn frames_mut(&mut self) -> &mut Vec<Frame> {
+        self.resolve();
+        &mut self.resolved.get_mut().unwrap().frames
+    }
+
+    // Original backtrace the frames were built from if retention was enabled
+    pub(crate) fn raw(&self) -> Option<&backtrace::Backtrace> {
+        self.resolve().raw.as_ref()
+    }
+
+    // Check if the symbols have been resolved yet
+    #[cfg(test)]
+    pub(crate) fn is_resolved(&self) -> bool {
+        self.resolved.get().is_some()
+    }
+
+    // Resolve the symbols of the captured stack once
+    fn resolve(&self) -> &Resolved {
+        self.resolved.get_or_init(|| match self.unresolved.clone() {
+            Some(mut raw) => {
+                raw.resolve();
+                Resolved { frames: simplify(&raw), raw: self.retain.then_some(raw) }
+            },
+            None => Resolved::default(),
+        })
+    }
+}
+
+// Frames that were built elsewhere e.g. transplanted from another error
+impl From<Vec<Frame>> for Stack {
+    fn from(frames: Vec<Frame>) -> Self {
+        Stack { resolved: OnceLock::from(Resolved { frames, raw: None }), ..Default::default() }
+    }
+}
+
+// Simplify the symbols of the given resolved backtrace into frames
+fn simplify(raw: &backtrace::Backtrace) -> Vec<Frame> {
+    raw.frames()
         .iter()
         .flat_map(|x| x.symbols())
         .map(|sym| Frame {
@@ -52,21 +178,79 @@ pub(crate) fn new() -> Vec<Frame> {
         .collect()
 }
 
-// Provide a convenient way to work with frame information
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Frame {
+// Collect the frames from the given provider stopping after `limit` raw frames.
+// The provider calls the given function with each raw frame in turn and should stop
+// walking the stack when it returns false.
+fn capture<T, P>(limit: Option<usize>, provider: P) -> Vec<T>
+where
+    P: FnOnce(&mut dyn FnMut(T) -> bool),
+{
+    let mut frames = Vec::new();
+    provider(&mut |frame| {
+        if limit.is_some_and(|x| frames.len() >= x) {
+            return false;
+        }
+        frames.push(frame);
+        limit.map_or(true, |x| frames.len() < x)
+    });
+    frames
+}
+
+// Get the raw frame limit from the environment if set
+fn frame_limit() -> Option<usize> {
+    std::env::var(WITCHER_FRAME_LIMIT).ok().and_then(|x| x.parse::<usize>().ok())
+}
+
+/// `Frame` provides the simplified information for a single backtrace frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
     pub symbol: String,      // name of the symbol or '<unknown>'
     pub filename: String,    // filename the symbole occurred in
     pub lineno: Option<u32>, // line number the symbol occurred on
     pub column: Option<u32>, // column number the symbol occurred on
 }
+#[cfg(feature = "serde")]
+impl serde::Serialize for Frame {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("Frame", 4)?;
+        state.serialize_field("symbol", &self.symbol)?;
+        state.serialize_field("filename", &self.filename)?;
+        state.serialize_field("lineno", &self.lineno)?;
+        state.serialize_field("column", &self.column)?;
+        state.end()
+    }
+}
+
 impl Frame {
-    // Check if this is a known rust dependency
+    /// Name of the symbol or `<unknown>` if it couldn't be resolved
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Filename the symbol is defined in simplified where possible
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Line number the frame is at if known
+    pub fn lineno(&self) -> Option<u32> {
+        self.lineno
+    }
+
+    /// Column number the frame is at if known
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+
+    /// Check if this is a known rust dependency
     pub fn is_dependency(&self) -> bool {
         if DEPENDENCY_SYM_PREFIXES.iter().any(|x| self.symbol.starts_with(x))
             || DEPENDENCY_SYM_CONTAINS.iter().any(|x| self.symbol.contains(x))
             || DEPENDENCY_FILE_PREFIXES.iter().any(|x| self.filename.starts_with(x))
             || DEPENDENCY_FILE_CONTAINS.iter().any(|x| self.filename.contains(x))
+            || FILTER_PREFIXES.read().unwrap().iter().any(|x| self.symbol.starts_with(x) || self.filename.starts_with(x))
+            || FILTER_CONTAINS.read().unwrap().iter().any(|x| self.symbol.contains(x) || self.filename.contains(x))
         {
             return true;
         }
@@ -75,7 +259,7 @@ impl Frame {
 }
 
 // Write out a shortened simplified path if possible
-fn simple_path(filename: Option<&Path>) -> String {
+pub(crate) fn simple_path(filename: Option<&Path>) -> String {
     let mut f = String::new();
     if let Some(file) = filename {
         // Strip off the current working directory to simplify the path
@@ -108,6 +292,18 @@ impl Omit for std::fmt::Result {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_custom_filters() {
+        let frame = |symbol: &str, filename: &str| Frame { symbol: symbol.to_string(), filename: filename.to_string(), lineno: Some(1), column: None };
+        assert!(!frame("gen_codegen::parse", "src/parse.rs").is_dependency());
+        assert!(!frame("app::run", "src/gen_out/run.rs").is_dependency());
+        add_filter_prefix("gen_codegen::");
+        add_filter_contains("/gen_out/");
+        assert!(frame("gen_codegen::parse", "src/parse.rs").is_dependency());
+        assert!(frame("app::run", "src/gen_out/run.rs").is_dependency());
+        assert!(!frame("app::run", "src/run.rs").is_dependency());
+    }
+
     #[test]
     fn test_frame_equality() {
         let mut frame1 = Frame {
@@ -124,14 +320,30 @@ mod tests {
             column: Some(2),
         };
 
-        assert_eq!(true, frame1 == frame2);
+        assert!(frame1 == frame2);
         assert_eq!(frame1, frame2);
 
         frame1.lineno = Some(3);
-        assert_eq!(true, frame1 != frame2);
+        assert!(frame1 != frame2);
         assert_ne!(frame1, frame2);
     }
 
+    #[test]
+    fn test_capture_limit() {
+        let provider = |f: &mut dyn FnMut(Frame) -> bool| {
+            for i in 0..100 {
+                let frame = Frame { symbol: format!("symbol{}", i), filename: String::from("filename"), lineno: None, column: None };
+                if !f(frame) {
+                    break;
+                }
+            }
+        };
+        assert_eq!(100, capture(None, provider).len());
+        assert_eq!(16, capture(Some(16), provider).len());
+        assert_eq!("symbol15", capture(Some(16), provider)[15].symbol);
+        assert_eq!(0, capture(Some(0), provider).len());
+    }
+
     #[test]
     fn test_simple_path() {
         let cwd = std::env::current_dir().unwrap();
@@ -1,14 +1,172 @@
-use crate::{backtrace::Frame, Result, StdError};
-use gory::*;
+use crate::{
+    backtrace::{simple_path, Frame, Stack},
+    term::Colorized,
+    Result, StdError,
+};
 use std::{
+    any::{Any, TypeId},
     convert::From,
     fmt::{self, Debug, Display, Formatter},
+    panic::Location,
+    path::Path,
+    thread::{self, ThreadId},
+    time::{Duration, SystemTime},
 };
 
 static ERROR_TYPE: &str = "witcher::Error";
 static STDERROR_TYPE: &str = "std::error::Error";
 static LONG_ERROR_TYPE: &str = "witcher::error::Error";
 
+/// Environment variable enabling collapsing of consecutive frames with the same symbol.
+///
+/// When set to a truthy value recursive calls will be written out once as `symbol (xN)`.
+pub const WITCHER_COLLAPSE_RECURSION: &str = "WITCHER_COLLAPSE_RECURSION";
+
+/// Environment variable setting the number of spaces to indent frame ` at:` lines with.
+///
+/// Defaults to `4` which aligns the location with the `symbol:` line above it.
+pub const WITCHER_FRAME_INDENT: &str = "WITCHER_FRAME_INDENT";
+
+/// Environment variable including all frames rather than just the filtered frames when
+/// serializing errors e.g. as JSON with the `serde` feature or via `Error::backtrace_string`.
+///
+/// When set to a truthy value dependency frames are included matching the `{:#?}` output.
+pub const WITCHER_FULLSTACK: &str = "WITCHER_FULLSTACK";
+
+/// Environment variable limiting the number of frames written out for each error.
+///
+/// When set to a number `N` only `N` frames will be written out per error. Unset means unlimited.
+pub const WITCHER_MAX_FRAMES: &str = "WITCHER_MAX_FRAMES";
+
+/// Environment variable choosing which end of the frames to keep when truncating.
+///
+/// Set to `top` to keep the frames closest to the entry point or `bottom` to keep the
+/// frames closest to the failure site. Defaults to `top`.
+pub const WITCHER_MAX_FRAMES_FROM: &str = "WITCHER_MAX_FRAMES_FROM";
+
+/// Environment variable including the time each error was created in the `Debug` output.
+///
+/// When set to a truthy value a `  time:` line is written out as RFC 3339 with the `time`
+/// feature or as seconds since the Unix epoch otherwise. Unset leaves the output unchanged.
+pub const WITCHER_TIMESTAMP: &str = "WITCHER_TIMESTAMP";
+
+// Environment variable names containing any of these are considered sensitive
+const SENSITIVE_ENV_KEYS: [&str; 6] = ["PASS", "SECRET", "TOKEN", "KEY", "CREDENTIAL", "AUTH"];
+
+/// `Format` selects one of the output levels `Error` supports.
+///
+/// The levels follow the same verbosity as the format options i.e. `{}  {:#}  {:?}  {:#?}`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `{}` writes out the first error message only
+    Normal,
+    /// `{:#}` writes out all error messages in the chain
+    Alternate,
+    /// `{:?}` writes out all error messaging with simplified backtracing
+    Debug,
+    /// `{:#?}` writes out all error messaging with full backtracing
+    AlternateDebug,
+}
+
+/// `Severity` indicates how serious an error is when it is ultimately reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// A failure the operation couldn't recover from, the default
+    Error,
+    /// A failure worth noting that didn't prevent the operation from continuing
+    Warning,
+    /// A failure that is purely informational
+    Info,
+}
+
+impl Severity {
+    // Label written out in place of ` error:` in the alternate display right aligned the same
+    fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => " error:",
+            Severity::Warning => "  warn:",
+            Severity::Info => "  info:",
+        }
+    }
+}
+
+// Optional metadata for an `Error` kept boxed to keep the size of `Result<T>` small
+#[derive(Default)]
+struct Metadata {
+    // Structured key/value context attached to the error level
+    fields: Vec<(String, String)>,
+
+    // Additional context lines attached to the error level in the order they were added
+    notes: Vec<String>,
+
+    // Exit code to use when this error terminates the process
+    code: Option<i32>,
+
+    // How serious the error is, unset means `Severity::Error`
+    severity: Option<Severity>,
+
+    // Domain level origin overriding where the error is displayed to come from
+    origin: Option<(String, u32)>,
+
+    // Position in the parsed input as a byte offset, line and column
+    position: Option<(usize, u32, u32)>,
+
+    // Free form labels for ad-hoc classification
+    tags: Vec<String>,
+
+    // Number of times this error occurred, unset means once
+    count: Option<usize>,
+
+    // Grouping key for error trackers, unset means generated from the chain
+    fingerprint: Option<String>,
+
+    // Recent operations leading up to the error in the order they happened
+    breadcrumbs: Vec<String>,
+
+    // Delays waited between retry attempts before giving up with this error
+    attempt_delays: Vec<Duration>,
+
+    // Process id and hostname of the process the error occurred in
+    process: Option<(u32, String)>,
+
+    // Snapshot of selected environment variables at the time of the error
+    env: Vec<(String, String)>,
+
+    // Source location of the call that created the error
+    location: Option<&'static Location<'static>>,
+
+    // Name if any and id of the thread that created the error
+    thread: Option<(Option<String>, ThreadId)>,
+
+    // Wall clock time the error was created at
+    created_at: Option<SystemTime>,
+
+    // Stack captured at creation with its cleaned up frames and the retained original
+    stack: Stack,
+
+    // Multiple independent causes in the case of an aggregate error
+    errors: Vec<Error>,
+
+    // Arbitrary user data attached to the error keyed by type
+    payloads: Vec<Box<dyn Any+Send+Sync>>,
+}
+
+impl Metadata {
+    // Capture the caller's location, the current thread and time and optionally the raw
+    // backtrace for a newly created error
+    #[track_caller]
+    fn capture(with_bt: bool) -> Box<Metadata> {
+        let current = thread::current();
+        Box::new(Metadata {
+            location: Some(Location::caller()),
+            thread: Some((current.name().map(String::from), current.id())),
+            created_at: Some(SystemTime::now()),
+            stack: if with_bt { Stack::capture() } else { Stack::default() },
+            ..Default::default()
+        })
+    }
+}
+
 /// `Error` is a wrapper providing additional context and chaining of errors.
 ///
 /// `Error` provides the following benefits
@@ -37,8 +195,12 @@ pub struct Error {
     // inner error is Some and is an external type else it will be `Error`.
     type_name: String,
 
-    // Backtrace frames that have been cleaned up
-    backtrace: Vec<Frame>,
+    // Type id follows the same rules as the type name above and is used to
+    // match against chain links without requiring a concrete type.
+    type_id: TypeId,
+
+    // Optional metadata attached to this error level
+    meta: Box<Metadata>,
 
     // The original error in the case where we're wrapping an external error or
     // an `Error` in the case where we're wrapping another `Error`.
@@ -46,36 +208,118 @@ pub struct Error {
 }
 impl Error {
     /// Create a new error instance wrapped in a result
+    #[track_caller]
     pub fn raw(msg: &str) -> Self {
         Self {
             pass: false,
             msg: msg.to_string(),
             type_name: String::from(ERROR_TYPE),
-            backtrace: crate::backtrace::new(),
+            type_id: TypeId::of::<Error>(),
+            meta: Metadata::capture(true),
+            inner: None,
+        }
+    }
+
+    /// Wrap the given error with a contextual message keeping `witcher::Error` as the type of
+    /// this level. Unlike `wrapr` which takes on the wrapped error's type name and id so that
+    /// e.g. `is`, `caused_by_any` and the `Debug` cause line report the concrete external type,
+    /// here the wrapped error is only reachable via `source` for downcasting and is reported
+    /// generically as a `std::error::Error`.
+    ///
+    /// ### Examples
+    /// ```
+    /// use witcher::prelude::*;
+    /// let err = Error::context("read failed", std::io::Error::other("oh no!"));
+    /// assert!(err.source().unwrap().is::<std::io::Error>());
+    /// ```
+    #[track_caller]
+    pub fn context<E>(msg: &str, err: E) -> Self
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        Self {
+            pass: false,
+            msg: msg.to_string(),
+            type_name: String::from(ERROR_TYPE),
+            type_id: TypeId::of::<Error>(),
+            meta: Metadata::capture(true),
+            inner: Some(Box::new(err)),
+        }
+    }
+
+    /// Create a new error instance without capturing a backtrace regardless of the environment.
+    /// This avoids the cost of capturing for known benign errors e.g. expected `NotFound` probes.
+    #[track_caller]
+    pub fn raw_no_bt(msg: &str) -> Self {
+        Self {
+            pass: false,
+            msg: msg.to_string(),
+            type_name: String::from(ERROR_TYPE),
+            type_id: TypeId::of::<Error>(),
+            meta: Metadata::capture(false),
             inner: None,
         }
     }
 
     /// Wrap the given error and include a contextual message for the error.
+    #[track_caller]
     pub fn wrapr<E>(err: E, msg: &str) -> Self
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        Error::wrapr_owned(err, msg.to_string())
+    }
+
+    // Wrap the given error taking ownership of the contextual message to avoid a copy
+    #[track_caller]
+    pub(crate) fn wrapr_owned<E>(err: E, msg: String) -> Self
     where
         E: StdError+Send+Sync+'static,
     {
         Self {
             pass: false,
-            msg: msg.to_string(),
+            msg,
             type_name: Error::name(&err),
-            backtrace: crate::backtrace::new(),
+            type_id: TypeId::of::<E>(),
+            meta: Metadata::capture(true),
             inner: Some(Box::new(err)),
         }
     }
 
+    /// Create a new aggregate error with the given message combining multiple causes.
+    #[track_caller]
+    pub fn aggregate(msg: &str, errors: Vec<Error>) -> Self {
+        let mut err = Error::raw(msg);
+        err.meta.errors = errors;
+        err
+    }
+
+    /// Create a new error for a failure at the given position in some parsed input.
+    /// The position is the byte `offset`, `line` and `col` within the input rather than a
+    /// source code location and is written out after the message as ` at input N:L:C`.
+    #[track_caller]
+    pub fn positioned(msg: &str, offset: usize, line: u32, col: u32) -> Error {
+        let mut err = Error::raw(msg);
+        err.meta.position = Some((offset, line, col));
+        err
+    }
+
+    /// Create a new error with the given message joining two independent failure chains.
+    /// Both chains are kept as causes of the join error and written out as separate branches
+    /// by `tree`, the `Debug` and alternate `Display` forms and the serialized output.
+    #[track_caller]
+    pub fn merge_contexts(primary: Error, secondary: Error, msg: &str) -> Error {
+        Error::aggregate(msg, vec![primary, secondary])
+    }
+
     /// Create a new error instance wrapped in a result
+    #[track_caller]
     pub fn new<T>(msg: &str) -> Result<T> {
         Err(Error::raw(msg))
     }
 
     /// Pass the given error through without a message
+    #[track_caller]
     pub fn pass<T, E>(err: E) -> Result<T>
     where
         E: StdError+Send+Sync+'static,
@@ -84,12 +328,14 @@ impl Error {
             pass: true,
             msg: "pass".to_string(),
             type_name: Error::name(&err),
-            backtrace: crate::backtrace::new(),
+            type_id: TypeId::of::<E>(),
+            meta: Metadata::capture(true),
             inner: Some(Box::new(err)),
         })
     }
 
     /// Wrap the given error and include a contextual message for the error.
+    #[track_caller]
     pub fn wrap<T, E>(err: E, msg: &str) -> Result<T>
     where
         E: StdError+Send+Sync+'static,
@@ -97,316 +343,2371 @@ impl Error {
         Err(Error::wrapr(err, msg))
     }
 
-    /// Return the first external error of the error chain for downcasting.
-    /// The intent is that when writing application code there are cases where your more
-    /// interested in reacting to an external failure.
-    /// If there is no external error then you'll get the last `Error` in the chain.
-    pub fn ext(&self) -> &(dyn StdError+'static) {
-        let mut stderr: &(dyn StdError+'static) = self;
-        let mut source = self.source();
-        while let Some(err) = source {
-            stderr = err;
-            if !err.is::<Error>() {
-                break;
-            }
-            source = err.source();
-        }
-        stderr
+    /// Start building a new error with the given message and structured fields.
+    ///
+    /// ### Examples
+    /// ```
+    /// use witcher::prelude::*;
+    /// let err = Error::build("request failed").field("request_id", "42").wrap(std::io::Error::other("oh no!")).done();
+    /// assert_eq!(&[("request_id".to_string(), "42".to_string())], err.fields());
+    /// ```
+    #[track_caller]
+    pub fn build(msg: &str) -> ErrorBuilder {
+        ErrorBuilder { err: Error::raw(msg) }
     }
 
-    /// Return the last of the error chain for downcasting.
-    /// This will follow the chain of source errors down to the last and return it.
-    /// If this error is the only error it will be returned instead.
-    pub fn last(&self) -> &(dyn StdError+'static) {
-        let mut err: &(dyn StdError+'static) = self;
-        let mut source = self.source();
-        while let Some(e) = source {
-            err = e;
-            source = e.source();
-        }
+    /// Wrap the given error with multiple context messages at a single wrap site.
+    /// The first message is the primary message returned by `Display` while the rest are
+    /// kept as notes written out in order on their own `  note:` lines by the alternate
+    /// `Display` and `Debug` output directly after the primary message.
+    ///
+    /// ### Examples
+    /// ```
+    /// use witcher::prelude::*;
+    /// let err = Error::wrap_all(std::io::Error::other("oh no!"), &["read failed", "resource=foo"]);
+    /// assert_eq!("read failed", err.to_string());
+    /// assert_eq!(&["resource=foo".to_string()], err.notes());
+    /// ```
+    #[track_caller]
+    pub fn wrap_all<E>(err: E, msgs: &[&str]) -> Self
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        let mut err = Error::wrapr(err, msgs.first().copied().unwrap_or_default());
+        err.meta.notes = msgs.iter().skip(1).map(|x| x.to_string()).collect();
         err
     }
 
-    /// Implemented directly on the `Error` type to reduce casting required
-    pub fn is<T: StdError+'static>(&self) -> bool {
-        if self.pass && self.inner.is_some() {
-            <dyn StdError+'static>::is::<T>(self.source().unwrap())
-        } else {
-            <dyn StdError+'static>::is::<T>(self)
-        }
+    /// Add a note to this error written out on its own line after the message.
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.meta.notes.push(note.to_string());
+        self
     }
 
-    /// Implemented directly on the `Error` type to reduce casting required
-    pub fn downcast_ref<T: StdError+'static>(&self) -> Option<&T> {
-        if self.pass && self.inner.is_some() {
-            <dyn StdError+'static>::downcast_ref::<T>(self.source().unwrap())
-        } else {
-            <dyn StdError+'static>::downcast_ref::<T>(self)
-        }
+    /// Return the notes attached to this error in the order they were added.
+    pub fn notes(&self) -> &[String] {
+        &self.meta.notes
     }
 
-    /// Implemented directly on the `Error` type to reduce casting required
-    pub fn source(&self) -> Option<&(dyn StdError+'static)> {
-        self.as_ref().source()
+    /// Attach the given error as the cause of this error.
+    /// This overwrites any existing inner error and updates the type to the given error's.
+    pub fn with_inner<E>(mut self, err: E) -> Self
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        self.type_name = Error::name(&err);
+        self.type_id = TypeId::of::<E>();
+        self.inner = Some(Box::new(err));
+        self
     }
 
-    // Get the message or the inner message depending on if a pass was used to construct this `Error`
-    fn msg(&self) -> String {
-        if self.pass {
-            match self.source() {
-                Some(err) => format!("{}", err),
-                None => String::new(),
-            }
-        } else {
-            self.msg.clone()
+    /// Wrap this error with multiple context messages at once.
+    /// The messages are ordered outermost first and each becomes its own `Error` layer but
+    /// unlike calling `wrap` repeatedly no additional backtraces are captured.
+    pub fn with_context_chain(self, msgs: &[&str]) -> Self {
+        let mut err = self;
+        for msg in msgs.iter().rev() {
+            err = Self {
+                pass: false,
+                msg: msg.to_string(),
+                type_name: String::from(ERROR_TYPE),
+                type_id: TypeId::of::<Error>(),
+                meta: Box::default(),
+                inner: Some(Box::new(err)),
+            };
         }
+        err
     }
 
-    // Extract the name of the given error type and perform some clean up on the type
-    fn name<T>(_: T) -> String {
-        let mut name = std::any::type_name::<T>().to_string();
-
-        // Strip off prefixes
-        if name.starts_with('&') {
-            name = String::from(name.trim_start_matches('&'));
-        }
+    /// Convert this error into a `Result<T>` i.e. `Err(self)`
+    pub fn into_result<T>(self) -> Result<T> {
+        Err(self)
+    }
 
-        if name.starts_with("dyn ") {
-            name = String::from(name.trim_start_matches("dyn "));
-        }
+    /// Annotate this error as having occurred `n` times rather than storing `n` copies.
+    /// A count greater than one is rendered as ` (occurred N times)` after the message.
+    pub fn with_count(mut self, n: usize) -> Self {
+        self.meta.count = Some(n);
+        self
+    }
 
-        // Strip off suffixes
-        name = String::from(name.split('<').next().unwrap_or("<unknown>"));
+    /// Get the number of times this error occurred, defaults to `1`
+    pub fn count(&self) -> usize {
+        self.meta.count.unwrap_or(1)
+    }
 
-        // Hide full Error path
-        if name == LONG_ERROR_TYPE {
-            name = String::from(ERROR_TYPE);
+    /// Set the message to the given default only when the current message is empty.
+    /// An empty pass through error will be converted into a normal wrapping error.
+    pub fn or_msg(mut self, default: &str) -> Self {
+        if self.msg().is_empty() {
+            self.pass = false;
+            self.msg = default.to_string();
         }
-
-        name
+        self
     }
 
-    // Write out external errors
-    fn write_std(&self, f: &mut Formatter<'_>, stderr: &dyn StdError) -> fmt::Result {
-        let mut buf = format!(" cause: {}: {}", self.type_name.red(), stderr.to_string().red());
-        let mut source = stderr.source();
-        while let Some(inner) = source {
-            if !buf.ends_with('\n') {
-                buf += &"\n";
-            }
-            buf += &format!(" cause: {}: {}", STDERROR_TYPE.red(), inner.to_string().red());
-            source = inner.source();
-        }
-        if !buf.ends_with('\n') {
-            buf += &"\n";
+    /// Set the top message to the given context only when this error has none of its own.
+    /// Unlike `or_msg` a pass through error is always given the context rather than keeping
+    /// the message of the error it passes through, which avoids wrapping it a second time.
+    pub fn wrap_if_empty(mut self, msg: &str) -> Self {
+        if self.pass || self.msg.is_empty() {
+            self.pass = false;
+            self.msg = msg.to_string();
         }
-        write!(f, "{}", buf)
+        self
     }
 
-    fn write_frames(&self, f: &mut Formatter<'_>, parent: Option<&Error>, fullstack: bool) -> fmt::Result {
-        let frames: Vec<&Frame> = if !fullstack {
-            let frames: Vec<&Frame> = self.backtrace.iter().filter(|x| !x.is_dependency()).collect();
-            match parent {
-                Some(parent) => {
-                    let len = frames.len();
-                    let plen = parent.backtrace.iter().filter(|x| !x.is_dependency()).count();
-                    frames.into_iter().take(len - plen).collect::<Vec<&Frame>>()
-                },
-                _ => frames,
-            }
+    /// Attach a key/value field to this error for structured context.
+    pub fn with_field(mut self, key: &str, value: &str) -> Self {
+        self.meta.fields.push((key.to_string(), value.to_string()));
+        self
+    }
 
-        // Fullstack `true` means don't filter anything
-        } else {
-            self.backtrace.iter().collect()
-        };
+    /// Return the key/value fields attached to this error.
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.meta.fields
+    }
 
-        let len = frames.len();
-        for (i, frame) in frames.iter().enumerate() {
-            writeln!(f, "symbol: {}", frame.symbol.cyan())?;
-            write!(f, "    at: {}", frame.filename)?;
+    /// Set the exit code to use when this error terminates the process.
+    pub fn with_code(mut self, code: i32) -> Self {
+        self.meta.code = Some(code);
+        self
+    }
 
-            if let Some(line) = frame.lineno {
-                write!(f, ":{}", line)?;
-                if let Some(column) = frame.column {
-                    write!(f, ":{}", column)?;
-                }
-            }
-            if i + 1 < len {
-                writeln!(f)?;
-            }
-        }
-        Ok(())
+    /// Return the exit code set on this error if any.
+    pub fn code(&self) -> Option<i32> {
+        self.meta.code
     }
-}
 
-// External trait implementations
-// -------------------------------------------------------------------------------------------------
+    /// Return the causes combined in this aggregate error.
+    pub fn errors(&self) -> &[Error] {
+        &self.meta.errors
+    }
 
-impl AsRef<dyn StdError> for Error {
-    fn as_ref(&self) -> &(dyn StdError+'static) {
+    /// Attach arbitrary data to this error for consumption by error handlers.
+    /// Payloads are keyed by type so attaching a payload replaces any of the same type.
+    pub fn with_payload<P: Any+Send+Sync>(mut self, payload: P) -> Self {
+        self.meta.payloads.retain(|x| !x.is::<P>());
+        self.meta.payloads.push(Box::new(payload));
         self
     }
-}
 
-impl StdError for Error {
-    fn source(&self) -> Option<&(dyn StdError+'static)> {
-        match &self.inner {
-            Some(x) => Some(&**x),
-            None => None,
-        }
+    /// Return a reference to the attached payload of type `P` if any.
+    pub fn payload<P: Any+Send+Sync>(&self) -> Option<&P> {
+        self.meta.payloads.iter().find_map(|x| x.downcast_ref::<P>())
     }
-}
 
-/// Provides the same formatting for output as Display but includes the fullstack trace.
-impl Debug for Error {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let fullstack = f.alternate();
+    /// Remove and return the attached payload of type `P` by value if any.
+    /// Payloads of other types are left attached.
+    pub fn take_payload<P: Any+Send+Sync>(&mut self) -> Option<P> {
+        let i = self.meta.payloads.iter().position(|x| x.is::<P>())?;
+        self.meta.payloads.remove(i).downcast::<P>().ok().map(|x| *x)
+    }
 
-        // Push all `Error` instances to a vec then reverse
-        let mut errors: Vec<&Error> = Vec::new();
-        let mut source = self.source();
-        errors.push(self);
-        while let Some(stderr_ref) = source {
-            if let Some(err) = stderr_ref.downcast_ref::<Error>() {
-                errors.push(err);
-                source = stderr_ref.source();
-            } else {
-                break;
-            }
-        }
-        errors = errors.into_iter().rev().collect();
+    /// Tag this error with a value of a user defined kind e.g. an application error enum or a
+    /// unit struct per category e.g. `with_kind(Timeout)` to later check with `has_kind`.
+    /// The kind is stored as a payload so tagging again with the same type replaces it.
+    pub fn with_kind<K: Any+Send+Sync+Copy>(self, kind: K) -> Self {
+        self.with_payload(kind)
+    }
 
-        // Pop them back off LIFO style
-        let len = errors.len();
-        for (i, err) in errors.iter().enumerate() {
-            let parent: Option<&Error> = if i + 1 < len {
-                Some(errors[i + 1])
-            } else {
-                None
-            };
+    /// Return the value of the user defined kind `K` this error was tagged with if any.
+    pub fn kind<K: Any+Send+Sync+Copy>(&self) -> Option<K> {
+        self.payload::<K>().copied()
+    }
 
-            // Write out the error wrapper
-            writeln!(f, " error: {}: {}", ERROR_TYPE.red(), err.msg().red())?;
+    /// Check if this error was tagged with the given value of a user defined kind.
+    pub fn is_kind<K: Any+Send+Sync+Copy+PartialEq>(&self, kind: K) -> bool {
+        self.kind::<K>() == Some(kind)
+    }
 
-            // Write out any std errors in order
-            if i == 0 {
-                if let Some(stderr) = if self.pass {
-                    (*err).source().and_then(|x| x.source())
-                } else {
-                    (*err).source()
-                } {
-                    err.write_std(f, stderr)?;
-                }
-            }
+    /// Check if any `Error` in the chain was tagged with a kind of type `K` regardless of value.
+    /// This allows for classifying errors by type e.g. `err.has_kind::<Timeout>()`.
+    pub fn has_kind<K: Any+Send+Sync+Copy>(&self) -> bool {
+        self.chain().filter_map(|x| x.downcast_ref::<Error>()).any(|x| x.kind::<K>().is_some())
+    }
 
-            // Write out the frames minus those in the wrapping error
-            err.write_frames(f, parent, fullstack)?;
-            if i + 1 < len {
-                writeln!(f)?;
-            }
-        }
-        Ok(())
+    /// Attach free form string labels to this error e.g. `["db", "retryable"]`.
+    /// Tags only apply to this error and are not inherited from inner errors.
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.meta.tags.extend(tags.iter().map(|x| x.to_string()));
+        self
     }
-}
 
-/// Provides formatting for output with frames filtered to just target code
+    /// Return the tags attached to this error.
+    pub fn tags(&self) -> &[String] {
+        &self.meta.tags
+    }
+
+    /// Record the delays waited between retry attempts before giving up with this error.
+    /// The backoff aware retry combinators e.g. `retry_with` record these automatically.
+    pub fn with_attempt_delays(mut self, delays: Vec<Duration>) -> Self {
+        self.set_attempt_delays(delays);
+        self
+    }
+
+    /// Return the delays waited between retry attempts before giving up with this error.
+    pub fn attempt_delays(&self) -> &[Duration] {
+        &self.meta.attempt_delays
+    }
+
+    // Record the delays waited between retry attempts in place
+    pub(crate) fn set_attempt_delays(&mut self, delays: Vec<Duration>) {
+        self.meta.attempt_delays = delays;
+    }
+
+    /// Record the process id and hostname to include in the `Debug` output.
+    /// This helps correlate errors from multiple processes across machines.
+    #[cfg(feature = "process-info")]
+    pub fn with_process_info(mut self) -> Self {
+        self.meta.process = Some((std::process::id(), crate::process::hostname()));
+        self
+    }
+
+    /// Snapshot the given environment variables to include in the `Debug` output.
+    /// Variables that aren't set are skipped and the values of sensitive variables e.g.
+    /// `API_TOKEN` or `DB_PASSWORD` are redacted.
+    pub fn capture_env(mut self, keys: &[&str]) -> Self {
+        for key in keys {
+            if let Ok(value) = std::env::var(key) {
+                let upper = key.to_uppercase();
+                let value = if SENSITIVE_ENV_KEYS.iter().any(|x| upper.contains(x)) { String::from("<redacted>") } else { value };
+                self.meta.env.push((key.to_string(), value));
+            }
+        }
+        self
+    }
+
+    /// Set the fingerprint error trackers use to group this error with others.
+    pub fn with_fingerprint(mut self, fp: &str) -> Self {
+        self.meta.fingerprint = Some(fp.to_string());
+        self
+    }
+
+    /// Return the fingerprint for grouping this error with others.
+    /// When not set one is generated from the chain's type names and the origin frame so
+    /// that structurally identical errors share the same fingerprint regardless of message.
+    /// The generated value is a 64 bit FNV-1a hash so it is stable across builds and releases.
+    pub fn fingerprint(&self) -> String {
+        if let Some(fp) = &self.meta.fingerprint {
+            return fp.clone();
+        }
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut fnv = |bytes: &[u8]| {
+            for byte in bytes.iter().chain(&[0xff]) {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        let mut err = self;
+        loop {
+            fnv(err.type_name.as_bytes());
+            match err.source().and_then(|x| x.downcast_ref::<Error>()) {
+                Some(inner) => err = inner,
+                None => break,
+            }
+        }
+        if let Some(frame) = err.origin_frame() {
+            fnv(frame.filename.as_bytes());
+            fnv(&frame.lineno.unwrap_or(0).to_le_bytes());
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// Check if the operation that failed with this error is worth retrying.
+    ///
+    /// An error is retryable when any `Error` in the chain carries a `retryable` tag or
+    /// when the first `std::io::Error` in the chain is of a transient kind e.g. `TimedOut`,
+    /// `Interrupted` or `WouldBlock`. Tags are checked first as they express intent directly.
+    pub fn is_retryable(&self) -> bool {
+        self.chain().filter_map(|x| x.downcast_ref::<Error>()).any(|x| x.meta.tags.iter().any(|t| t == "retryable"))
+            || matches!(
+                self.io_kind(),
+                Some(
+                    std::io::ErrorKind::TimedOut
+                        | std::io::ErrorKind::Interrupted
+                        | std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::ConnectionReset
+                        | std::io::ErrorKind::ConnectionAborted
+                )
+            )
+    }
+
+    /// Attach the recent operations that led up to this error in the order they happened.
+    /// These provide narrative context beyond the cause chain in the `Debug` output.
+    pub fn with_breadcrumbs(mut self, crumbs: Vec<String>) -> Self {
+        self.meta.breadcrumbs = crumbs;
+        self
+    }
+
+    /// Return the breadcrumbs attached to this error.
+    pub fn breadcrumbs(&self) -> &[String] {
+        &self.meta.breadcrumbs
+    }
+
+    /// Return the input position as a byte offset, line and column if this error was
+    /// created with `positioned`.
+    pub fn position(&self) -> Option<(usize, u32, u32)> {
+        self.meta.position
+    }
+
+    /// Override the displayed origin of this error with the given file and line.
+    /// This is independent of the backtrace and intended for domain level locations e.g. a
+    /// parser pointing into its input. It is written out in the `Debug` output as `origin:`.
+    pub fn with_source_location(mut self, file: &str, line: u32) -> Self {
+        self.meta.origin = Some((file.to_string(), line));
+        self
+    }
+
+    /// Return the overridden origin of this error if set.
+    pub fn source_location(&self) -> Option<(&str, u32)> {
+        self.meta.origin.as_ref().map(|(file, line)| (file.as_str(), *line))
+    }
+
+    /// Set the severity to report this error with.
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.meta.severity = Some(severity);
+        self
+    }
+
+    /// Return the severity of this error which defaults to `Severity::Error`.
+    pub fn severity(&self) -> Severity {
+        self.meta.severity.unwrap_or(Severity::Error)
+    }
+
+    /// Lower the severity of this error one step i.e. `Error` to `Warning` to `Info`.
+    /// An `Info` error stays `Info`.
+    ///
+    /// ### Examples
+    /// ```
+    /// use witcher::prelude::*;
+    /// let err = Error::raw("optional plugin failed").demote();
+    /// assert_eq!(witcher::Severity::Warning, err.severity());
+    /// ```
+    pub fn demote(self) -> Self {
+        let severity = match self.severity() {
+            Severity::Error => Severity::Warning,
+            _ => Severity::Info,
+        };
+        self.with_severity(severity)
+    }
+
+    /// Raise the severity of this error one step i.e. `Info` to `Warning` to `Error`.
+    /// An `Error` error stays `Error`.
+    pub fn promote(self) -> Self {
+        let severity = match self.severity() {
+            Severity::Info => Severity::Warning,
+            _ => Severity::Error,
+        };
+        self.with_severity(severity)
+    }
+
+    /// Return the process exit code for this error suitable for returning from `main`.
+    /// Codes larger than `255` are clamped to `255`. Codes of `0` or less, which would report
+    /// success or wrap around, and errors without a code use `ExitCode::FAILURE`.
+    pub fn exit_code(&self) -> std::process::ExitCode {
+        match self.meta.code {
+            Some(code) if code > 0 => std::process::ExitCode::from(code.min(255) as u8),
+            _ => std::process::ExitCode::FAILURE,
+        }
+    }
+
+    /// Copy the metadata from the given error into this error.
+    /// Metadata already set on this error wins and the other error's values only fill
+    /// in those that are unset e.g. fields are only copied for keys not already present.
+    pub fn with_metadata_from(mut self, other: &Error) -> Self {
+        for (key, value) in other.meta.fields.iter() {
+            if !self.meta.fields.iter().any(|(k, _)| k == key) {
+                self.meta.fields.push((key.clone(), value.clone()));
+            }
+        }
+        if self.meta.code.is_none() {
+            self.meta.code = other.meta.code;
+        }
+        if self.meta.severity.is_none() {
+            self.meta.severity = other.meta.severity;
+        }
+        if self.meta.origin.is_none() {
+            self.meta.origin = other.meta.origin.clone();
+        }
+        if self.meta.position.is_none() {
+            self.meta.position = other.meta.position;
+        }
+        if self.meta.tags.is_empty() {
+            self.meta.tags = other.meta.tags.clone();
+        }
+        if self.meta.breadcrumbs.is_empty() {
+            self.meta.breadcrumbs = other.meta.breadcrumbs.clone();
+        }
+        if self.meta.env.is_empty() {
+            self.meta.env = other.meta.env.clone();
+        }
+        if self.meta.process.is_none() {
+            self.meta.process = other.meta.process.clone();
+        }
+        self
+    }
+
+    /// Return the whole error chain as a single line joined with `: ` for log lines.
+    /// The line is written out without colors or backtrace frames.
+    pub fn to_line(&self) -> String {
+        self.chain_msgs().join(": ")
+    }
+
+    /// Return a one line summary of the whole error chain truncated to fit the given width.
+    /// Messages are joined with `: ` and when truncation is required the summary will end
+    /// with an ellipsis while still fitting within `width` characters.
+    pub fn summary(&self, width: usize) -> String {
+        let line = self.to_line();
+        if line.chars().count() <= width {
+            return line;
+        }
+        match width {
+            0 => String::new(),
+            _ => line.chars().take(width - 1).chain(std::iter::once('…')).collect(),
+        }
+    }
+
+    /// Return the kind of the first `std::io::Error` found in the error chain if any.
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        io_kind(self)
+    }
+
+    /// Return the `Debug` output with the layers in reverse order.
+    /// `Debug` writes the root cause and its frames first followed by each wrapping layer,
+    /// whereas this starts with the outermost context and ends with the root cause.
+    pub fn debug_reversed(&self) -> String {
+        format!("{:?}", Reversed(self))
+    }
+
+    /// Return just the backtrace frames of this error rendered without colors or messages
+    /// e.g. for attaching to a span. Dependency frames are filtered out unless
+    /// `WITCHER_FULLSTACK` is set to a truthy value.
+    pub fn backtrace_string(&self) -> String {
+        format!("{}", Frames(self, flag(WITCHER_FULLSTACK)))
+    }
+
+    /// Return the error chain rendered as an indented tree.
+    /// Single chains are indented linearly while aggregate causes branch with `├─`/`└─`
+    /// connectors or `|-`/`` `- `` connectors when color output is disabled.
+    pub fn tree(&self) -> String {
+        let mut buf = String::new();
+        write_tree(&mut buf, self, "", !Colorized::new().colorized);
+        buf
+    }
+
+    /// Return an RFC 5424 style syslog line for this error.
+    /// The priority uses the user-level facility with a severity mapped from the error's
+    /// `Severity` followed by the process id, the message chain and any fields as `key=value`.
+    #[cfg(feature = "syslog")]
+    pub fn to_syslog(&self) -> String {
+        let severity = match self.severity() {
+            Severity::Error => 3,
+            Severity::Warning => 4,
+            Severity::Info => 6,
+        };
+        let mut line = format!("<{}>1 - - - {} - - {}", 8 + severity, std::process::id(), self.chain_msgs().join(": "));
+        let mut source: Option<&(dyn StdError+'static)> = Some(self);
+        while let Some(stderr) = source {
+            if let Some(err) = stderr.downcast_ref::<Error>() {
+                for (key, value) in err.meta.fields.iter() {
+                    match value.contains(char::is_whitespace) || value.contains('"') {
+                        true => line += &format!(" {}={:?}", key, value),
+                        false => line += &format!(" {}={}", key, value),
+                    }
+                }
+            }
+            source = stderr.source();
+        }
+        line
+    }
+
+    /// Emit the error chain as a single line log record at the given level via the `log` crate.
+    /// At the `Debug` and `Trace` levels the backtrace frames are included on following lines.
+    #[cfg(feature = "log")]
+    pub fn log(&self, level: log::Level) {
+        let mut msg = self.to_line();
+        if level >= log::Level::Debug {
+            let frames = self.backtrace_string();
+            if !frames.is_empty() {
+                msg += "\n";
+                msg += &frames;
+            }
+        }
+        log::log!(level, "{}", msg);
+    }
+
+    /// Emit the error chain as a single line log record at the `Error` level.
+    #[cfg(feature = "log")]
+    pub fn log_error(&self) {
+        self.log(log::Level::Error)
+    }
+
+    /// Record the error as a `tracing` error event on the current span with the structured
+    /// fields `message`, `type_name` and `chain` i.e. the messages joined with `: `. When
+    /// `WITCHER_FULLSTACK` is set to a truthy value a `backtrace` field is included as well.
+    #[cfg(feature = "tracing")]
+    pub fn record(&self) {
+        let (msg, chain) = (self.msg(), self.to_line());
+        if flag(WITCHER_FULLSTACK) {
+            tracing::error!(type_name = self.type_name.as_str(), chain = chain.as_str(), backtrace = self.backtrace_string().as_str(), "{}", msg);
+        } else {
+            tracing::error!(type_name = self.type_name.as_str(), chain = chain.as_str(), "{}", msg);
+        }
+    }
+
+    /// Return the error as OpenTelemetry exception attributes for recording on a span.
+    /// The `exception.type` is the type of the deepest error in the chain, `exception.message`
+    /// is the message chain and `exception.stacktrace` is rendered from the deepest error's frames.
+    #[cfg(feature = "otel")]
+    pub fn otel_attributes(&self) -> Vec<(&'static str, String)> {
+        let mut deepest = self;
+        let mut source: Option<&(dyn StdError+'static)> = Some(self);
+        while let Some(stderr) = source {
+            if let Some(err) = stderr.downcast_ref::<Error>() {
+                deepest = err;
+            }
+            source = stderr.source();
+        }
+        let mut stacktrace = String::new();
+        for frame in deepest.frames().iter() {
+            if !stacktrace.is_empty() {
+                stacktrace += "\n";
+            }
+            stacktrace += &format!("{}\n    at {}", frame.symbol, frame.filename);
+            if let Some(line) = frame.lineno {
+                stacktrace += &format!(":{}", line);
+            }
+        }
+        vec![("exception.type", deepest.type_name.clone()), ("exception.message", self.chain_msgs().join(": ")), ("exception.stacktrace", stacktrace)]
+    }
+
+    /// Return the source location of the call that created this error.
+    /// This provides a cheap origin marker even when backtraces are disabled.
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.meta.location
+    }
+
+    /// Return the name of the thread that created this error if it was named.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.meta.thread.as_ref().and_then(|(name, _)| name.as_deref())
+    }
+
+    /// Return the id of the thread that created this error.
+    pub fn thread_id(&self) -> Option<ThreadId> {
+        self.meta.thread.as_ref().map(|(_, id)| *id)
+    }
+
+    /// Return the wall clock time this error was created at.
+    pub fn created_at(&self) -> Option<SystemTime> {
+        self.meta.created_at
+    }
+
+    /// Return the time this error was created at formatted as RFC 3339 in UTC.
+    #[cfg(feature = "time")]
+    pub fn created_at_rfc3339(&self) -> Option<String> {
+        self.meta.created_at.map(timestamp)
+    }
+
+    /// Check if backtraces are captured when creating errors.
+    /// Capturing is expensive so it can be disabled by setting `WITCHER_BACKTRACE=0` or, when
+    /// that is unset, `RUST_BACKTRACE=0` in which case errors are created without any frames.
+    pub fn backtrace_enabled() -> bool {
+        crate::backtrace::enabled()
+    }
+
+    /// Return the original backtrace if retention was enabled via `WITCHER_RAW_BACKTRACE`
+    /// when this error was created. This provides details the simplified frames omit
+    /// e.g. inlined frames and instruction addresses.
+    pub fn raw_backtrace(&self) -> Option<&backtrace::Backtrace> {
+        self.meta.stack.raw()
+    }
+
+    /// Return the backtrace frames captured for this error for custom rendering.
+    /// Use `Frame::is_dependency` to replicate the filtering done by the `Debug` output.
+    pub fn frames(&self) -> &[Frame] {
+        self.meta.stack.frames()
+    }
+
+    /// Replace the backtrace of this error with a copy of the `other` error's backtrace.
+    /// This preserves the original capture site when an error is reconstructed elsewhere.
+    pub fn with_backtrace_from(mut self, other: &Error) -> Self {
+        self.meta.stack = other.meta.stack.clone();
+        self
+    }
+
+    /// Transform every backtrace frame of every `Error` in the chain with the given function.
+    /// This is useful for redacting or rewriting paths at the data level.
+    pub fn map_frames<F: FnMut(Frame) -> Frame>(mut self, mut f: F) -> Self {
+        self.map_frames_mut(&mut f);
+        self
+    }
+
+    // Transform the frames of this error, its aggregated errors and its inner errors
+    fn map_frames_mut(&mut self, f: &mut dyn FnMut(Frame) -> Frame) {
+        let frames = self.meta.stack.frames_mut();
+        *frames = std::mem::take(frames).into_iter().map(&mut *f).collect();
+        for err in self.meta.errors.iter_mut() {
+            err.map_frames_mut(f);
+        }
+        if let Some(err) = self.inner.as_mut().and_then(|x| x.downcast_mut::<Error>()) {
+            err.map_frames_mut(f);
+        }
+    }
+
+    /// Convert this error into an `anyhow::Error` for interop with code using anyhow.
+    /// The error chain is preserved so anyhow's `chain` yields each layer in turn and
+    /// downcasting to the original errors continues to work.
+    #[cfg(feature = "anyhow")]
+    pub fn into_anyhow(self) -> anyhow::Error {
+        anyhow::Error::new(self)
+    }
+
+    /// Return a canonical form of this error for comparison, caching and deduplication.
+    /// Non-deterministic metadata like backtraces and their file paths, the creating thread and
+    /// the creation time are dropped and type names are lowercased for every `Error` in the chain.
+    /// The file of an overridden origin is kept but shortened relative to the current directory
+    /// the same way as frame paths.
+    pub fn canonicalize(mut self) -> Self {
+        self.meta.stack = Stack::default();
+        self.meta.location = None;
+        self.meta.origin = self.meta.origin.map(|(file, line)| (simple_path(Some(Path::new(&file))), line));
+        self.meta.created_at = None;
+        self.meta.thread = None;
+        self.type_name = self.type_name.to_lowercase();
+        self.meta.errors = self.meta.errors.into_iter().map(|x| x.canonicalize()).collect();
+        self.inner = match self.inner.take() {
+            Some(inner) => match inner.downcast::<Error>() {
+                Ok(err) => Some(Box::new(err.canonicalize())),
+                Err(inner) => Some(inner),
+            },
+            None => None,
+        };
+        self
+    }
+
+    /// Render this error into the given buffer using the given format.
+    /// The buffer is cleared first allowing a single buffer to be reused across errors.
+    pub fn render_into(&self, buf: &mut String, fmt: Format) {
+        use std::fmt::Write;
+        buf.clear();
+        let _ = match fmt {
+            Format::Normal => write!(buf, "{}", self),
+            Format::Alternate => write!(buf, "{:#}", self),
+            Format::Debug => write!(buf, "{:?}", self),
+            Format::AlternateDebug => write!(buf, "{:#?}", self),
+        };
+    }
+
+    /// Return the count of each type in the error chain preserving first seen order.
+    /// External errors are named by the `Error` wrapping them, while deeper external sources
+    /// whose concrete type can't be determined are counted as `std::error::Error`.
+    pub fn group_by_type(&self) -> Vec<(String, usize)> {
+        let mut groups: Vec<(String, usize)> = Vec::new();
+        let mut parent: Option<&Error> = None;
+        let mut source: Option<&(dyn StdError+'static)> = Some(self);
+        while let Some(stderr) = source {
+            let name = match stderr.downcast_ref::<Error>() {
+                Some(_) => ERROR_TYPE.to_string(),
+                None => match parent {
+                    Some(err) => err.source_type_name().to_string(),
+                    None => STDERROR_TYPE.to_string(),
+                },
+            };
+            match groups.iter_mut().find(|(x, _)| *x == name) {
+                Some((_, count)) => *count += 1,
+                None => groups.push((name, 1)),
+            }
+            parent = stderr.downcast_ref::<Error>();
+            source = stderr.source();
+        }
+        groups
+    }
+
+    /// Return this error as a standard error trait object.
+    /// This is the entry point for manually walking the chain via `source`.
+    pub fn std(&self) -> &(dyn StdError+'static) {
+        self
+    }
+
+    /// Return an iterator over the error chain starting with this error and then following
+    /// `source` all the way down including both `Error` instances and external errors.
+    /// The chain is walked lazily as the iterator is consumed.
+    pub fn chain(&self) -> impl Iterator<Item = &(dyn StdError+'static)> {
+        std::iter::successors(Some(self as &(dyn StdError+'static)), |&x| x.source())
+    }
+
+    /// Return an iterator over the display messages of the error chain from the outermost to
+    /// the innermost error. `Error` levels yield their message while external errors yield
+    /// their `Display` output ready for printing e.g. in a custom UI.
+    pub fn display_chain(&self) -> impl Iterator<Item = String> {
+        self.chain_msgs().into_iter()
+    }
+
+    /// Return the first external error of the error chain for downcasting.
+    /// The intent is that when writing application code there are cases where your more
+    /// interested in reacting to an external failure.
+    /// If there is no external error then you'll get the last `Error` in the chain.
+    pub fn ext(&self) -> &(dyn StdError+'static) {
+        let mut stderr: &(dyn StdError+'static) = self;
+        let mut source = self.source();
+        while let Some(err) = source {
+            stderr = err;
+            if !err.is::<Error>() {
+                break;
+            }
+            source = err.source();
+        }
+        stderr
+    }
+
+    /// Return the last of the error chain for downcasting.
+    /// This will follow the chain of source errors down to the last and return it.
+    /// If this error is the only error it will be returned instead.
+    pub fn last(&self) -> &(dyn StdError+'static) {
+        let mut err: &(dyn StdError+'static) = self;
+        let mut source = self.source();
+        while let Some(e) = source {
+            err = e;
+            source = e.source();
+        }
+        err
+    }
+
+    /// Return the root cause of the error chain i.e. the deepest error following `source`.
+    /// This is an alias for `last` that reads better when reacting to the underlying failure.
+    pub fn root_cause(&self) -> &(dyn StdError+'static) {
+        self.last()
+    }
+
+    /// Check if the root cause of the error chain is of the given type.
+    pub fn root_cause_is<T: StdError+'static>(&self) -> bool {
+        self.root_cause().is::<T>()
+    }
+
+    /// Downcast the root cause of the error chain to the given type.
+    pub fn root_cause_downcast<T: StdError+'static>(&self) -> Option<&T> {
+        self.root_cause().downcast_ref::<T>()
+    }
+
+    /// Check if any link in the error chain matches any of the given type ids.
+    /// Only `Error` instances and the errors they directly wrap can be identified as the
+    /// `std::error::Error` trait doesn't expose the type id of external sources.
+    pub fn caused_by_any(&self, ids: &[TypeId]) -> bool {
+        let mut source: Option<&(dyn StdError+'static)> = Some(self);
+        while let Some(stderr) = source {
+            if let Some(err) = stderr.downcast_ref::<Error>() {
+                if (!err.pass && ids.contains(&TypeId::of::<Error>())) || ids.contains(&err.type_id) {
+                    return true;
+                }
+            }
+            source = stderr.source();
+        }
+        false
+    }
+
+    /// Check if this error was raised from the same code location as the `other` error.
+    /// The location is the file and line of the first non-dependency frame of each.
+    pub fn is_same_origin(&self, other: &Error) -> bool {
+        match (self.origin_frame(), other.origin_frame()) {
+            (Some(x), Some(y)) => x.filename == y.filename && x.lineno == y.lineno,
+            _ => false,
+        }
+    }
+
+    /// Implemented directly on the `Error` type to reduce casting required
+    pub fn is<T: StdError+'static>(&self) -> bool {
+        if self.pass && self.inner.is_some() {
+            <dyn StdError+'static>::is::<T>(self.source().unwrap())
+        } else {
+            <dyn StdError+'static>::is::<T>(self)
+        }
+    }
+
+    /// Implemented directly on the `Error` type to reduce casting required
+    pub fn downcast_ref<T: StdError+'static>(&self) -> Option<&T> {
+        if self.pass && self.inner.is_some() {
+            <dyn StdError+'static>::downcast_ref::<T>(self.source().unwrap())
+        } else {
+            <dyn StdError+'static>::downcast_ref::<T>(self)
+        }
+    }
+
+    /// Check if any error in the chain reachable via `source` is of the given type.
+    pub fn is_chain<T: StdError+'static>(&self) -> bool {
+        self.chain().any(|x| x.is::<T>())
+    }
+
+    /// Return the first error in the chain reachable via `source` of the given type.
+    pub fn find<T: StdError+'static>(&self) -> Option<&T> {
+        self.chain().find_map(|x| x.downcast_ref::<T>())
+    }
+
+    /// Downcast the error this error wraps to the given type taking ownership of it.
+    /// On mismatch this error is returned unchanged so that another type can be tried.
+    pub fn downcast<T: StdError+'static>(mut self) -> std::result::Result<T, Error> {
+        match self.inner.take().map(|x| x.downcast::<T>()) {
+            Some(Ok(inner)) => Ok(*inner),
+            Some(Err(inner)) => {
+                self.inner = Some(inner);
+                Err(self)
+            },
+            None => Err(self),
+        }
+    }
+
+    /// Downcast the same as `downcast_ref` but return the given default on mismatch
+    pub fn downcast_ref_or<'a, T: StdError+'static>(&'a self, default: &'a T) -> &'a T {
+        self.downcast_ref::<T>().unwrap_or(default)
+    }
+
+    /// Implemented directly on the `Error` type to reduce casting required
+    pub fn source(&self) -> Option<&(dyn StdError+'static)> {
+        self.as_ref().source()
+    }
+
+    // Get the message or the inner message depending on if a pass was used to construct this `Error`
+    fn msg(&self) -> String {
+        let mut msg = if self.pass {
+            match self.source() {
+                Some(err) => format!("{}", err),
+                None => String::new(),
+            }
+        } else {
+            self.msg.clone()
+        };
+        if let Some((offset, line, col)) = self.meta.position {
+            msg += &format!(" at input {}:{}:{}", offset, line, col);
+        }
+        if self.count() > 1 {
+            msg += &format!(" (occurred {} times)", self.count());
+        }
+        msg
+    }
+
+    // Get the first non-dependency frame i.e. where the error was raised
+    fn origin_frame(&self) -> Option<&Frame> {
+        self.frames().iter().find(|x| !x.is_dependency())
+    }
+
+    // Get the messages for the whole chain without any coloring
+    fn chain_msgs(&self) -> Vec<String> {
+        let mut msgs = vec![self.msg()];
+        let mut source = if self.pass {
+            self.source().and_then(|x| x.source())
+        } else {
+            self.source()
+        };
+        while let Some(stderr) = source {
+            match stderr.downcast_ref::<Error>() {
+                Some(err) => msgs.push(err.msg()),
+                _ => msgs.push(stderr.to_string()),
+            }
+            source = stderr.source();
+        }
+        msgs
+    }
+
+    // Extract the name of the given error type and perform some clean up on the type
+    fn name<T>(_: T) -> String {
+        let mut name = std::any::type_name::<T>().to_string();
+
+        // Strip off prefixes
+        if name.starts_with('&') {
+            name = String::from(name.trim_start_matches('&'));
+        }
+
+        if name.starts_with("dyn ") {
+            name = String::from(name.trim_start_matches("dyn "));
+        }
+
+        // Strip off suffixes
+        name = String::from(name.split('<').next().unwrap_or("<unknown>"));
+
+        // Hide full Error path
+        if name == LONG_ERROR_TYPE {
+            name = String::from(ERROR_TYPE);
+        }
+
+        name
+    }
+
+    // Get the type name of the external error this error directly wraps which is unknown when
+    // this error was created with `context` as it keeps `Error` as its own type
+    fn source_type_name(&self) -> &str {
+        match self.type_id == TypeId::of::<Error>() {
+            true => STDERROR_TYPE,
+            false => &self.type_name,
+        }
+    }
+
+    // Write out the notes then the fields each on their own line
+    fn write_fields(&self, buf: &mut String) {
+        for note in self.meta.notes.iter() {
+            *buf += &format!("\n  note: {}", note);
+        }
+        for (key, value) in self.meta.fields.iter() {
+            *buf += &format!("\n field: {}={}", key, value);
+        }
+    }
+
+    // Write out each independent branch of an aggregate error in the alternate display form
+    fn write_branches(&self, buf: &mut String) {
+        for (i, child) in self.meta.errors.iter().enumerate() {
+            *buf += &format!("\nbranch: {}\n{:#}", i + 1, child);
+        }
+    }
+
+    // Write out all `Error` layers with their frames, root cause first unless reversed.
+    // The invoked command line is only written out when `command` is set for the top level.
+    pub(crate) fn write_debug(&self, f: &mut Formatter<'_>, fullstack: bool, reversed: bool, command: bool) -> fmt::Result {
+        let c = Colorized::new();
+
+        // Write out the invoked command line if captured
+        if command {
+            crate::process::write_command(f)?;
+        }
+
+        // Write out the thread that created the error for the alternate form
+        if fullstack {
+            match &self.meta.thread {
+                Some((Some(name), _)) => writeln!(f, "thread: {}", name)?,
+                Some((None, id)) => writeln!(f, "thread: {:?}", id)?,
+                None => {},
+            }
+        }
+
+        // Write out the time the error was created if requested
+        if flag(WITCHER_TIMESTAMP) {
+            if let Some(created_at) = self.meta.created_at {
+                writeln!(f, "  time: {}", timestamp(created_at))?;
+            }
+        }
+
+        self.write_levels(f, &c, fullstack, reversed)
+    }
+
+    // Write out each `Error` layer of the chain along with the branches of any aggregate layer
+    fn write_levels(&self, f: &mut Formatter<'_>, c: &Colorized, fullstack: bool, reversed: bool) -> fmt::Result {
+        // Push all `Error` instances to a vec then reverse
+        let mut errors: Vec<&Error> = Vec::new();
+        let mut source = self.source();
+        errors.push(self);
+        while let Some(stderr_ref) = source {
+            if let Some(err) = stderr_ref.downcast_ref::<Error>() {
+                errors.push(err);
+                source = stderr_ref.source();
+            } else {
+                break;
+            }
+        }
+        errors = errors.into_iter().rev().collect();
+
+        // Pop them back off LIFO style or in wrapping order when reversed
+        let len = errors.len();
+        let order: Vec<usize> = if reversed {
+            (0..len).rev().collect()
+        } else {
+            (0..len).collect()
+        };
+        for (n, &i) in order.iter().enumerate() {
+            let err = errors[i];
+            let parent: Option<&Error> = if i + 1 < len {
+                Some(errors[i + 1])
+            } else {
+                None
+            };
+
+            // Write out the error wrapper
+            writeln!(f, " error: {}: {}", c.type_name(ERROR_TYPE), c.message(&err.msg()))?;
+            for note in err.meta.notes.iter() {
+                writeln!(f, "  note: {}", note)?;
+            }
+            if let Some((file, line)) = &err.meta.origin {
+                writeln!(f, "origin: {}:{}", file, line)?;
+            }
+            if i + 1 == len && !err.meta.tags.is_empty() {
+                writeln!(f, "  tags: {}", err.meta.tags.join(", "))?;
+            }
+            if i + 1 == len && !err.meta.attempt_delays.is_empty() {
+                writeln!(f, "delays: {}", err.meta.attempt_delays.iter().map(|x| format!("{:?}", x)).collect::<Vec<String>>().join(", "))?;
+            }
+            if i + 1 == len {
+                if let Some((pid, host)) = &err.meta.process {
+                    writeln!(f, "   pid: {} host: {}", pid, host)?;
+                }
+                for (key, value) in err.meta.env.iter() {
+                    writeln!(f, "   env: {}={}", key, value)?;
+                }
+            }
+            if i + 1 == len && !err.meta.breadcrumbs.is_empty() {
+                writeln!(f, " breadcrumbs:")?;
+                for (j, crumb) in err.meta.breadcrumbs.iter().enumerate() {
+                    writeln!(f, "   {}. {}", j + 1, crumb)?;
+                }
+            }
+
+            // Write out any std errors in order
+            if i == 0 {
+                if let Some(stderr) = if self.pass {
+                    err.source().and_then(|x| x.source())
+                } else {
+                    err.source()
+                } {
+                    err.write_std(f, c, stderr)?;
+                }
+            }
+
+            // Write out the frames minus those in the wrapping error
+            err.write_frames(f, c, parent, fullstack)?;
+
+            // Write out each independent branch of an aggregate error
+            for (j, child) in err.meta.errors.iter().enumerate() {
+                writeln!(f, "\nbranch: {}", j + 1)?;
+                child.write_levels(f, c, fullstack, reversed)?;
+            }
+            if n + 1 < len {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Write out external errors
+    fn write_std(&self, f: &mut Formatter<'_>, c: &Colorized, stderr: &dyn StdError) -> fmt::Result {
+        let mut buf = format!(" cause: {}: {}", c.type_name(self.source_type_name()), c.cause(&stderr.to_string()));
+        let mut source = stderr.source();
+        while let Some(inner) = source {
+            if !buf.ends_with('\n') {
+                buf += "\n";
+            }
+            buf += &format!(" cause: {}: {}", c.type_name(STDERROR_TYPE), c.cause(&inner.to_string()));
+            source = inner.source();
+        }
+        if !buf.ends_with('\n') {
+            buf += "\n";
+        }
+        write!(f, "{}", buf)
+    }
+
+    // Get the frames to write out for this error minus those in the wrapping error
+    fn frames_for(&self, parent: Option<&Error>, fullstack: bool) -> Vec<&Frame> {
+        if !fullstack {
+            let frames: Vec<&Frame> = self.frames().iter().filter(|x| !x.is_dependency()).collect();
+            match parent {
+                // Drop the trailing frames shared with the wrapping error as those are shown
+                // with it. Comparing by function rather than just counting avoids an underflow
+                // when the wrapping error has more frames e.g. wrapped deeper in the stack.
+                Some(parent) => {
+                    let pframes: Vec<&Frame> = parent.frames().iter().filter(|x| !x.is_dependency()).collect();
+                    let shared = frames.iter().rev().zip(pframes.iter().rev()).take_while(|(x, y)| x.symbol == y.symbol && x.filename == y.filename).count();
+                    let len = frames.len() - shared;
+                    frames.into_iter().take(len).collect::<Vec<&Frame>>()
+                },
+                _ => frames,
+            }
+
+        // Fullstack `true` means don't filter anything
+        } else {
+            self.frames().iter().collect()
+        }
+    }
+
+    fn write_frames(&self, f: &mut Formatter<'_>, c: &Colorized, parent: Option<&Error>, fullstack: bool) -> fmt::Result {
+        let frames = self.frames_for(parent, fullstack);
+
+        // Truncate the frames keeping the requested end
+        let frames = match std::env::var(WITCHER_MAX_FRAMES).ok().and_then(|x| x.parse::<usize>().ok()) {
+            Some(max) if frames.len() > max => match std::env::var(WITCHER_MAX_FRAMES_FROM).unwrap_or_default().to_lowercase().as_str() {
+                "bottom" => frames[frames.len() - max..].to_vec(),
+                _ => frames[..max].to_vec(),
+            },
+            _ => frames,
+        };
+
+        // Collapse consecutive frames with the same symbol e.g. recursion
+        let collapse = flag(WITCHER_COLLAPSE_RECURSION);
+        let mut groups: Vec<(&Frame, usize)> = Vec::new();
+        for frame in frames {
+            match groups.last_mut() {
+                Some((last, count)) if collapse && last.symbol == frame.symbol => *count += 1,
+                _ => groups.push((frame, 1)),
+            }
+        }
+
+        let indent = " ".repeat(std::env::var(WITCHER_FRAME_INDENT).ok().and_then(|x| x.parse::<usize>().ok()).unwrap_or(4));
+        let len = groups.len();
+        for (i, (frame, count)) in groups.iter().enumerate() {
+            let symbol = if frame.is_dependency() { c.dim_frame(&frame.symbol) } else { c.frame(&frame.symbol) };
+            write!(f, "symbol: {}", symbol)?;
+            if *count > 1 {
+                write!(f, " (x{})", count)?;
+            }
+            writeln!(f)?;
+            write!(f, "{}at: {}", indent, frame.filename)?;
+
+            if let Some(line) = frame.lineno {
+                write!(f, ":{}", line)?;
+                if let Some(column) = frame.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+            if i + 1 < len {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Format the given time as RFC 3339 in UTC
+#[cfg(feature = "time")]
+fn timestamp(time: SystemTime) -> String {
+    time::OffsetDateTime::from(time).format(&time::format_description::well_known::Rfc3339).unwrap_or_default()
+}
+
+// Format the given time as seconds since the Unix epoch
+#[cfg(not(feature = "time"))]
+fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:09}", since.as_secs(), since.subsec_nanos())
+}
+
+// Write out the given error and its causes as a tree with the given line prefix
+fn write_tree(buf: &mut String, stderr: &(dyn StdError+'static), prefix: &str, ascii: bool) {
+    let (branch, last, pipe) = if ascii { ("|- ", "`- ", "|  ") } else { ("├─ ", "└─ ", "│  ") };
+    match stderr.downcast_ref::<Error>() {
+        Some(err) if !err.meta.errors.is_empty() => {
+            *buf += &err.msg();
+            let len = err.meta.errors.len();
+            for (i, child) in err.meta.errors.iter().enumerate() {
+                let (connector, indent) = if i + 1 < len { (branch, pipe) } else { (last, "   ") };
+                *buf += &format!("\n{}{}", prefix, connector);
+                write_tree(buf, child, &format!("{}{}", prefix, indent), ascii);
+            }
+        },
+        err => {
+            let source = match err {
+                Some(err) => {
+                    *buf += &err.msg();
+                    if err.pass {
+                        err.source().and_then(|x| x.source())
+                    } else {
+                        err.source()
+                    }
+                },
+                None => {
+                    *buf += &stderr.to_string();
+                    stderr.source()
+                },
+            };
+            if let Some(source) = source {
+                let prefix = format!("{}  ", prefix);
+                *buf += &format!("\n{}", prefix);
+                write_tree(buf, source, &prefix, ascii);
+            }
+        },
+    }
+}
+
+// Check if the given environment variable is set to a truthy value
+pub(crate) fn flag(key: &str) -> bool {
+    !matches!(std::env::var(key).unwrap_or_default().to_lowercase().as_str(), "" | "0" | "false")
+}
+
+// Convert the given error into an `Error` passing it through if it isn't one already
+pub(crate) fn into_error<E>(err: E) -> Error
+where
+    E: StdError+Send+Sync+'static,
+{
+    let err: Box<dyn Any+Send+Sync> = Box::new(err);
+    match err.downcast::<Error>() {
+        Ok(err) => *err,
+        Err(err) => match err.downcast::<E>() {
+            Ok(err) => Error::pass::<(), E>(*err).unwrap_err(),
+            Err(_) => unreachable!(),
+        },
+    }
+}
+
+// Find the kind of the first `std::io::Error` in the chain starting with the given error
+pub(crate) fn io_kind(err: &(dyn StdError+'static)) -> Option<std::io::ErrorKind> {
+    let mut source = Some(err);
+    while let Some(stderr) = source {
+        if let Some(ioerr) = stderr.downcast_ref::<std::io::Error>() {
+            return Some(ioerr.kind());
+        }
+        source = stderr.source();
+    }
+    None
+}
+
+/// `ErrorBuilder` assembles an `Error` with structured fields one piece at a time.
+///
+/// Create one with `Error::build` and finalize it with `done`.
+pub struct ErrorBuilder {
+    err: Error,
+}
+impl ErrorBuilder {
+    /// Attach a key/value field to the error being built
+    pub fn field(mut self, key: &str, value: &str) -> Self {
+        self.err = self.err.with_field(key, value);
+        self
+    }
+
+    /// Set the given error as the cause of the error being built
+    pub fn wrap<E>(mut self, err: E) -> Self
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        self.err = self.err.with_inner(err);
+        self
+    }
+
+    /// Finish building and return the error
+    pub fn done(self) -> Error {
+        self.err
+    }
+}
+
+// External trait implementations
+// -------------------------------------------------------------------------------------------------
+
+impl AsRef<dyn StdError> for Error {
+    fn as_ref(&self) -> &(dyn StdError+'static) {
+        self
+    }
+}
+
+// A blanket `impl<E: StdError> From<E> for Error` would conflict with the reflexive
+// `impl<T> From<T> for T` as `Error` itself implements `StdError`. Instead the standard
+// library error types are converted explicitly as pass through errors so that `?` works
+// on them directly. Other error types still need `wrap` or `pass`. Conversions the other way
+// into `Box<dyn StdError>` and `Box<dyn StdError+Send+Sync>` are already provided by the
+// standard library's blanket impls so `?` works in functions returning boxed errors.
+macro_rules! impl_from {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Error {
+                #[track_caller]
+                fn from(err: $t) -> Self {
+                    Error::pass::<(), $t>(err).unwrap_err()
+                }
+            }
+        )*
+    };
+}
+impl_from!(
+    std::io::Error,
+    fmt::Error,
+    std::num::ParseIntError,
+    std::num::ParseFloatError,
+    std::num::TryFromIntError,
+    std::str::ParseBoolError,
+    std::str::Utf8Error,
+    std::string::FromUtf8Error,
+    std::char::ParseCharError,
+    std::env::VarError,
+    std::array::TryFromSliceError,
+    std::time::SystemTimeError
+);
+
+// Serialize the error chain as nested objects each with a `cause` following `source`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        JsonLink { stderr: self, type_name: STDERROR_TYPE, parent: None, fullstack: flag(WITCHER_FULLSTACK) }.serialize(serializer)
+    }
+}
+
+// Serializable view of a single link in the error chain
+#[cfg(feature = "serde")]
+struct JsonLink<'a> {
+    stderr: &'a (dyn StdError+'static),
+    type_name: &'a str,        // type name to use when the link is an external error
+    parent: Option<&'a Error>, // wrapping error whose frames are omitted
+    fullstack: bool,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for JsonLink<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(None)?;
+        let next = match self.stderr.downcast_ref::<Error>() {
+            Some(err) => {
+                map.serialize_entry("message", &err.msg())?;
+                map.serialize_entry("type_name", if err.pass { err.type_name.as_str() } else { ERROR_TYPE })?;
+                map.serialize_entry("backtrace", &err.frames_for(self.parent, self.fullstack))?;
+                if self.parent.is_none() {
+                    map.serialize_entry("fingerprint", &err.fingerprint())?;
+                }
+                if !err.meta.fields.is_empty() {
+                    map.serialize_entry("fields", &err.meta.fields.iter().cloned().collect::<std::collections::BTreeMap<String, String>>())?;
+                }
+                if let Some(code) = err.meta.code {
+                    map.serialize_entry("code", &code)?;
+                }
+                if !err.meta.tags.is_empty() {
+                    map.serialize_entry("tags", &err.meta.tags)?;
+                }
+                if let Some((offset, line, column)) = err.meta.position {
+                    map.serialize_entry("position", &[offset as u64, line as u64, column as u64])?;
+                }
+                if !err.meta.errors.is_empty() {
+                    let errors: Vec<JsonLink> = err.meta.errors.iter().map(|x| JsonLink { stderr: x, type_name: STDERROR_TYPE, parent: None, fullstack: self.fullstack }).collect();
+                    map.serialize_entry("errors", &errors)?;
+                }
+                let source = if err.pass { err.source().and_then(|x| x.source()) } else { err.source() };
+                source.map(|x| JsonLink { stderr: x, type_name: if err.pass { STDERROR_TYPE } else { err.source_type_name() }, parent: Some(err), fullstack: self.fullstack })
+            },
+            None => {
+                map.serialize_entry("message", &self.stderr.to_string())?;
+                map.serialize_entry("type_name", self.type_name)?;
+                self.stderr.source().map(|x| JsonLink { stderr: x, type_name: STDERROR_TYPE, parent: self.parent, fullstack: self.fullstack })
+            },
+        };
+        if let Some(next) = next {
+            map.serialize_entry("cause", &next)?;
+        }
+        map.end()
+    }
+}
+
+/// Errors are equal when each `Error` level's message, type, backtrace and metadata are equal
+/// and their sources are equal. External errors are compared by their `Display` output.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        if self.pass != other.pass
+            || self.msg != other.msg
+            || self.type_name != other.type_name
+            || self.frames() != other.frames()
+            || self.meta.fields != other.meta.fields
+            || self.meta.code != other.meta.code
+            || self.meta.severity != other.meta.severity
+            || self.meta.origin != other.meta.origin
+            || self.meta.location != other.meta.location
+            || self.meta.position != other.meta.position
+            || self.meta.tags != other.meta.tags
+            || self.meta.count != other.meta.count
+            || self.meta.fingerprint != other.meta.fingerprint
+            || self.meta.breadcrumbs != other.meta.breadcrumbs
+            || self.meta.notes != other.meta.notes
+            || self.meta.env != other.meta.env
+            || self.meta.process != other.meta.process
+            || self.meta.attempt_delays != other.meta.attempt_delays
+            || self.meta.errors != other.meta.errors
+        {
+            return false;
+        }
+        match (self.source(), other.source()) {
+            (None, None) => true,
+            (Some(x), Some(y)) => match (x.downcast_ref::<Error>(), y.downcast_ref::<Error>()) {
+                (Some(x), Some(y)) => x == y,
+                (None, None) => x.to_string() == y.to_string(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError+'static)> {
+        match &self.inner {
+            Some(x) => Some(&**x),
+            None => None,
+        }
+    }
+}
+
+/// Provides the same formatting for output as Display but includes the fullstack trace.
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.write_debug(f, f.alternate(), false, true)
+    }
+}
+
+// Provides the `Debug` formatting with the layers in reverse order
+struct Reversed<'a>(&'a Error);
+impl Debug for Reversed<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.write_debug(f, f.alternate(), true, true)
+    }
+}
+
+// Provides the frames of an error without colors optionally including dependency frames
+struct Frames<'a>(&'a Error, bool);
+impl Display for Frames<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.write_frames(f, &Colorized::plain(), None, self.1)
+    }
+}
+
+/// Provides formatting for output with frames filtered to just target code
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         if !f.alternate() {
             return write!(f, "{}", self.msg());
         }
 
-        // Write out more detail
+        // Write out more detail
+        let c = Colorized::new();
+        let mut buf = String::new();
+        buf += &format!("{} {}", self.severity().label(), c.message(&self.msg()));
+        if let Some(loc) = self.meta.location {
+            buf += &format!("\n    at: {}:{}", loc.file(), loc.line());
+        }
+        self.write_fields(&mut buf);
+        self.write_branches(&mut buf);
+
+        // Traverse the whole chain
+        let mut source = if self.pass {
+            self.source().and_then(|x| x.source())
+        } else {
+            self.source()
+        };
+        let mut prev = self.msg();
+        while let Some(stderr) = source {
+            let (msg, err) = match stderr.downcast_ref::<Error>() {
+                Some(err) => (err.msg(), Some(err)),
+                _ => (stderr.to_string(), None),
+            };
+            source = stderr.source();
+
+            // Skip a blank cause line or one duplicating the line immediately before it
+            if (msg.is_empty() || msg == prev) && err.map_or(true, |x| x.meta.fields.is_empty() && x.meta.notes.is_empty() && x.meta.errors.is_empty()) {
+                continue;
+            }
+            if !buf.ends_with('\n') {
+                buf += "\n";
+            }
+            buf += &format!(" cause: {}", c.cause(&msg));
+            if let Some(err) = err {
+                err.write_fields(&mut buf);
+                err.write_branches(&mut buf);
+            }
+            prev = msg;
+        }
+        write!(f, "{}", buf)
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ENV_LOCK;
+    use std::env;
+
+    // Disable backtrace and colors
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+    pub fn initialize() {
+        INIT.call_once(|| {
+            env::set_var(gory::TERM_COLOR, "0");
+            env::set_var("RUST_BACKTRACE", "0");
+        });
+    }
+
+    struct TestError {
+        msg: String,
+        inner: Option<Box<TestError>>,
+    }
+    #[cfg(not(tarpaulin_include))]
+    impl Debug for TestError {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            write!(f, "{}", self.msg)
+        }
+    }
+    impl Display for TestError {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            write!(f, "{}", self.msg)
+        }
+    }
+    impl StdError for TestError {
+        fn source(&self) -> Option<&(dyn StdError+'static)> {
+            match &self.inner {
+                Some(x) => Some(x as &dyn StdError),
+                None => None,
+            }
+        }
+    }
+
+    // Test alte
+    #[test]
+    fn test_output_levels() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+
+        // Test standard output
+        assert_eq!("wrapped", format!("{}", Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped")));
+
+        // Test alternate standard output
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
+        assert_eq!(format!(" error: wrapped{}\n cause: cause", at(&err)), format!("{:#}", err));
+
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
+        assert_eq!(" error: witcher::Error: wrapped\n cause: witcher::error::tests::TestError: cause\n", format!("{:?}", err).split("symbol").next().unwrap());
+        let err = Error::wrapr(
+            TestError {
+                msg: "cause".to_string(),
+                inner: Some(Box::new(TestError { msg: "cause2".to_string(), inner: None })),
+            },
+            "wrapped",
+        );
+        let thread = format!("thread: {}\n", thread::current().name().unwrap());
+        assert_eq!(format!("{} error: witcher::Error: wrapped\n cause: witcher::error::tests::TestError: cause\n cause: std::error::Error: cause2\n", thread), format!("{:#?}", err).split("symbol").next().unwrap());
+    }
+
+    #[test]
+    fn test_chained_cause() {
+        initialize();
+        let err = TestError {
+            msg: "cause 1".to_string(),
+            inner: Some(Box::new(TestError {
+                msg: "cause 2".to_string(),
+                inner: Some(Box::new(TestError { msg: "cause 3".to_string(), inner: None })),
+            })),
+        };
+
+        let err = Error::wrapr(err, "wrapped");
+        assert_eq!(format!(" error: wrapped{}\n cause: cause 1\n cause: cause 2\n cause: cause 3", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_ext_and_last() {
+        initialize();
+        let err = TestError {
+            msg: "cause 1".to_string(),
+            inner: Some(Box::new(TestError {
+                msg: "cause 2".to_string(),
+                inner: Some(Box::new(TestError { msg: "cause 3".to_string(), inner: None })),
+            })),
+        };
+        assert_eq!("foo", Error::wrapr(TestError { msg: "cause 1".to_string(), inner: None }, "foo").to_string());
+        assert_eq!("cause 1", Error::wrapr(TestError { msg: "cause 1".to_string(), inner: None }, "foo").ext().to_string());
+        assert_eq!("cause 3", Error::wrapr(err, "foo").last().to_string());
+    }
+
+    #[test]
+    fn test_root_cause() {
+        initialize();
+        let err = TestError {
+            msg: "cause 1".to_string(),
+            inner: Some(Box::new(TestError {
+                msg: "cause 2".to_string(),
+                inner: Some(Box::new(TestError { msg: "cause 3".to_string(), inner: None })),
+            })),
+        };
+        assert_eq!("cause 3", Error::wrapr(err, "foo").root_cause().to_string());
+
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "inner"), "foo");
+        assert_eq!("cause", err.root_cause().to_string());
+        assert!(err.root_cause_is::<TestError>());
+        assert!(!err.root_cause_is::<std::io::Error>());
+        assert_eq!("cause", err.root_cause_downcast::<TestError>().unwrap().msg);
+        assert!(err.root_cause_downcast::<std::io::Error>().is_none());
+
+        let err = Error::raw("oh no!");
+        assert_eq!("oh no!", err.root_cause().to_string());
+        assert!(err.root_cause_is::<Error>());
+    }
+
+    #[test]
+    fn test_from_std_errors() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        fn io() -> Result<()> {
+            Err(std::io::Error::other("oh no!"))?;
+            Ok(())
+        }
+        let line = line!() - 3;
+        fn parse(val: &str) -> Result<i32> {
+            Ok(val.parse::<i32>()?)
+        }
+        let err = io().unwrap_err();
+        assert_eq!("oh no!", err.to_string());
+        assert_eq!(line, err.location().unwrap().line());
+        assert!(err.is::<std::io::Error>());
+        assert_eq!(Some(std::io::ErrorKind::Other), err.io_kind());
+
+        assert_eq!(42, parse("42").unwrap());
+        let err = parse("foo").unwrap_err();
+        assert!(err.downcast_ref::<std::num::ParseIntError>().is_some());
+        assert!(format!("{:?}", err).starts_with(" error: witcher::Error: invalid digit found in string\n"));
+    }
+
+    #[test]
+    fn test_dedup_cause_lines() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "not found".to_string(), inner: None }, "not found"), "failed to load");
+        assert_eq!(format!(" error: failed to load{}\n cause: not found", at(&err)), format!("{:#}", err));
+
+        let err = Error::wrapr(TestError { msg: "not found".to_string(), inner: None }, "not found");
+        assert_eq!(format!(" error: not found{}", at(&err)), format!("{:#}", err));
+
+        // Only consecutive duplicates are collapsed
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "a".to_string(), inner: None }, "b"), "a");
+        assert_eq!(format!(" error: a{}\n cause: b\n cause: a", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_into_boxed() {
+        initialize();
+        fn boxed() -> std::result::Result<(), Box<dyn StdError>> {
+            Err(Error::wrapr(Error::raw("cause"), "wrapped"))?;
+            Ok(())
+        }
+        fn boxed_send() -> std::result::Result<(), Box<dyn StdError+Send+Sync+'static>> {
+            Err(Error::wrapr(Error::raw("cause"), "wrapped"))?;
+            Ok(())
+        }
+        let err = boxed().unwrap_err();
+        assert_eq!("wrapped", err.to_string());
+        assert_eq!("cause", err.source().unwrap().to_string());
+        let err = boxed_send().unwrap_err();
+        assert_eq!("wrapped", err.to_string());
+        assert!(err.downcast_ref::<Error>().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "anyhow")]
+    fn test_into_anyhow() {
+        initialize();
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "inner");
+        let err = Error::wrapr(err, "outer").into_anyhow();
+        assert_eq!(vec!["outer", "inner", "cause"], err.chain().map(|x| x.to_string()).collect::<Vec<String>>());
+        assert_eq!("outer: inner: cause", format!("{:#}", err));
+        assert!(err.downcast_ref::<Error>().is_some());
+    }
+
+    #[test]
+    fn test_pass() {
+        initialize();
+        let err = Error::pass::<(), _>(std::io::Error::other("oh no!")).unwrap_err();
+        assert_eq!("std::io::error::Error", err.type_name);
+        assert_eq!("oh no!", err.to_string());
+        assert_eq!("oh no!", err.downcast_ref::<std::io::Error>().unwrap().to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serialize() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut inner = Error::wrapr(TestError { msg: "cause 1".to_string(), inner: Some(Box::new(TestError { msg: "cause 2".to_string(), inner: None })) }, "inner");
+        inner.meta.stack = vec![frame("foo::run", 20), frame("foo::main", 30), frame("std::rt::lang_start", 10)].into();
+        let mut err = Error::wrapr(inner, "outer").with_tags(&["db"]).with_fingerprint("fp");
+        err.meta.stack = vec![frame("foo::main", 30)].into();
+
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!("outer", json["message"]);
+        assert_eq!("witcher::Error", json["type_name"]);
+        assert_eq!("fp", json["fingerprint"]);
+        assert_eq!(serde_json::json!(["db"]), json["tags"]);
+        assert_eq!(serde_json::json!([{"symbol": "foo::main", "filename": "src/foo.rs", "lineno": 30, "column": null}]), json["backtrace"]);
+
+        let cause = &json["cause"];
+        assert_eq!("inner", cause["message"]);
+        assert_eq!(1, cause["backtrace"].as_array().unwrap().len());
+        assert!(cause.get("fingerprint").is_none());
+        assert_eq!(serde_json::json!({"message": "cause 1", "type_name": "witcher::error::tests::TestError", "cause": {"message": "cause 2", "type_name": "std::error::Error"}}), cause["cause"]);
+
+        env::set_var(WITCHER_FULLSTACK, "1");
+        let json = serde_json::to_value(&err).unwrap();
+        env::remove_var(WITCHER_FULLSTACK);
+        assert_eq!(3, json["cause"]["backtrace"].as_array().unwrap().len());
+
+        let json = serde_json::to_value(Error::positioned("unexpected token", 42, 3, 7)).unwrap();
+        assert_eq!(serde_json::json!([42, 3, 7]), json["position"]);
+
+        let json = serde_json::to_value(Error::merge_contexts(Error::raw("write cache"), Error::raw("connection refused"), "sync failed")).unwrap();
+        let errors = json["errors"].as_array().unwrap();
+        assert_eq!(vec!["write cache", "connection refused"], errors.iter().map(|x| x["message"].as_str().unwrap()).collect::<Vec<&str>>());
+    }
+
+    #[test]
+    fn test_location() {
+        initialize();
+        let err = Error::raw("oh no!");
+        assert_eq!((file!(), line!() - 1), (err.location().unwrap().file(), err.location().unwrap().line()));
+        let err = Error::new::<()>("oh no!").unwrap_err();
+        assert_eq!(line!() - 1, err.location().unwrap().line());
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
+        assert_eq!(line!() - 1, err.location().unwrap().line());
+        let err = Error::wrap::<(), _>(TestError { msg: "cause".to_string(), inner: None }, "wrapped").unwrap_err();
+        assert_eq!(line!() - 1, err.location().unwrap().line());
+        assert!(format!("{:#}", err).starts_with(&format!(" error: wrapped\n    at: {}:{}\n", file!(), line!() - 2)));
+        assert!(err.canonicalize().location().is_none());
+    }
+
+    #[test]
+    fn test_std() {
+        initialize();
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
+        assert_eq!("wrapped", err.std().to_string());
+        assert_eq!(err.source().map(|x| x.to_string()), err.std().source().map(|x| x.to_string()));
+        assert!(err.std().source().unwrap().is::<TestError>());
+        assert!(Error::raw("oh no!").std().source().is_none());
+    }
+
+    #[test]
+    fn test_chain() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "cause 1".to_string(), inner: Some(Box::new(TestError { msg: "cause 2".to_string(), inner: None })) }, "inner"), "outer");
+        assert_eq!(4, err.chain().count());
+        assert_eq!(vec!["outer", "inner", "cause 1", "cause 2"], err.chain().map(|x| x.to_string()).collect::<Vec<String>>());
+        assert!(err.chain().nth(2).unwrap().is::<TestError>());
+        assert_eq!(1, Error::raw("oh no!").chain().count());
+    }
+
+    #[test]
+    fn test_wrap_all() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::wrap_all(std::io::Error::other("oh no!"), &["op failed", "resource=foo", "attempt=2"]);
+        assert_eq!("op failed", err.to_string());
+        assert_eq!(&["resource=foo".to_string(), "attempt=2".to_string()], err.notes());
+        assert_eq!(format!(" error: op failed{}\n  note: resource=foo\n  note: attempt=2\n cause: oh no!", at(&err)), format!("{:#}", err));
+        assert!(format!("{:?}", err).contains(" error: witcher::Error: op failed\n  note: resource=foo\n  note: attempt=2\n"));
+
+        let err = Error::wrapr(Error::raw("cause").with_note("first").with_note("second"), "wrapped");
+        assert_eq!("wrapped", err.to_string());
+        assert_eq!(format!(" error: wrapped{}\n cause: cause\n  note: first\n  note: second", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_context() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::context("read failed", std::io::Error::other("oh no!"));
+        assert_eq!("witcher::Error", err.type_name);
+        assert!(err.is::<Error>());
+        assert!(!err.is::<std::io::Error>());
+        assert!(err.source().unwrap().is::<std::io::Error>());
+        assert!(err.is_chain::<std::io::Error>());
+        assert_eq!("read failed", err.to_string());
+        assert_eq!(format!(" error: read failed{}\n cause: oh no!", at(&err)), format!("{:#}", err));
+        assert!(format!("{:?}", err).starts_with(" error: witcher::Error: read failed\n cause: std::error::Error: oh no!\n"));
+
+        // Unlike wrapr which takes on the wrapped error's type
+        let err = Error::wrapr(std::io::Error::other("oh no!"), "read failed");
+        assert_eq!("std::io::error::Error", err.type_name);
+        assert!(format!("{:?}", err).starts_with(" error: witcher::Error: read failed\n cause: std::io::error::Error: oh no!\n"));
+    }
+
+    #[test]
+    fn test_downcast() {
+        initialize();
+        let err = Error::wrapr(std::io::Error::from(std::io::ErrorKind::NotFound), "wrapped");
+        let err = err.downcast::<TestError>().unwrap_err();
+        assert_eq!("wrapped", err.to_string());
+        assert!(err.source().unwrap().is::<std::io::Error>());
+        let inner = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(std::io::ErrorKind::NotFound, inner.kind());
+
+        let err = Error::raw("oh no!").downcast::<std::io::Error>().unwrap_err();
+        assert_eq!("oh no!", err.to_string());
+    }
+
+    #[test]
+    fn test_is_chain_and_find() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "cause 1".to_string(), inner: Some(Box::new(TestError { msg: "cause 2".to_string(), inner: None })) }, "inner"), "outer");
+        assert!(!err.is::<TestError>());
+        assert!(err.is_chain::<TestError>());
+        assert!(err.is_chain::<Error>());
+        assert!(!err.is_chain::<std::io::Error>());
+        assert_eq!("cause 1", err.find::<TestError>().unwrap().msg);
+        assert!(err.find::<std::io::Error>().is_none());
+
+        let err = Error::wrapr(Error::wrapr(std::io::Error::from(std::io::ErrorKind::NotFound), "inner"), "outer");
+        assert!(err.is_chain::<std::io::Error>());
+        assert_eq!(std::io::ErrorKind::NotFound, err.find::<std::io::Error>().unwrap().kind());
+    }
+
+    #[test]
+    fn test_thread() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let (err, id) = thread::Builder::new().name("worker".to_string()).spawn(|| (Error::wrapr(std::io::Error::other("oh no!"), "wrapped"), thread::current().id())).unwrap().join().unwrap();
+        assert_eq!(Some("worker"), err.thread_name());
+        assert_eq!(Some(id), err.thread_id());
+        assert!(format!("{:#?}", err).starts_with("thread: worker\n error: witcher::Error: wrapped\n"));
+        assert!(!format!("{:?}", err).contains("thread: "));
+
+        let err = thread::spawn(|| Error::raw("oh no!")).join().unwrap();
+        assert_eq!(None, err.thread_name());
+        assert!(format!("{:#?}", err).starts_with(&format!("thread: {:?}\n", err.thread_id().unwrap())));
+    }
+
+    #[test]
+    fn test_created_at() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let before = SystemTime::now();
+        let (err1, err2) = (Error::raw("first"), Error::wrapr(std::io::Error::other("oh no!"), "second"));
+        assert!(before <= err1.created_at().unwrap());
+        assert!(err1.created_at().unwrap() <= err2.created_at().unwrap());
+        assert!(err2.created_at().unwrap() <= SystemTime::now());
+        assert!(!format!("{:?}", err1).contains("  time: "));
+
+        env::set_var(WITCHER_TIMESTAMP, "1");
+        let output = format!("{:?}", err1);
+        env::remove_var(WITCHER_TIMESTAMP);
+        assert!(output.starts_with("  time: "));
+        assert!(output.contains("\n error: witcher::Error: first"));
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_created_at_rfc3339() {
+        initialize();
+        let mut err = Error::raw("oh no!");
+        err.meta.created_at = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        assert_eq!(Some("2023-11-14T22:13:20Z".to_string()), err.created_at_rfc3339());
+    }
+
+    #[test]
+    fn test_display_chain() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "cause 1".to_string(), inner: Some(Box::new(TestError { msg: "cause 2".to_string(), inner: None })) }, "inner").with_count(2), "outer");
+        assert_eq!(vec!["outer", "inner (occurred 2 times)", "cause 1", "cause 2"], err.display_chain().collect::<Vec<String>>());
+        assert_eq!(vec!["oh no!"], Error::pass::<(), _>(std::io::Error::other("oh no!")).unwrap_err().display_chain().collect::<Vec<String>>());
+    }
+
+    #[test]
+    fn test_caused_by_any() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "inner"), "outer");
+        assert!(err.caused_by_any(&[TypeId::of::<std::io::Error>(), TypeId::of::<TestError>()]));
+        assert!(!err.caused_by_any(&[TypeId::of::<std::io::Error>(), TypeId::of::<fmt::Error>()]));
+    }
+
+    #[test]
+    fn test_or_msg() {
+        initialize();
+        assert_eq!("default", Error::raw("").or_msg("default").to_string());
+        assert_eq!("foo", Error::raw("foo").or_msg("default").to_string());
+        let err = Error::pass::<(), _>(TestError { msg: "".to_string(), inner: None }).unwrap_err().or_msg("default");
+        assert_eq!("default", err.to_string());
+        assert_eq!(format!(" error: default{}", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_wrap_if_empty() {
+        initialize();
+        assert_eq!("context", Error::raw("").wrap_if_empty("context").to_string());
+        assert_eq!("foo", Error::raw("foo").wrap_if_empty("context").to_string());
+
+        let err = Error::pass::<(), _>(TestError { msg: "cause".to_string(), inner: None }).unwrap_err().wrap_if_empty("context");
+        assert_eq!(format!(" error: context{}\n cause: cause", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_build() {
+        initialize();
+        let inner = Error::build("query failed").field("table", "users").wrap(TestError { msg: "cause".to_string(), inner: None }).done();
+        let err = Error::build("request failed").field("request_id", "42").field("user", "geralt").wrap(inner).done();
+        assert_eq!(&[("request_id".to_string(), "42".to_string()), ("user".to_string(), "geralt".to_string())], err.fields());
+        let inner = err.source().unwrap().downcast_ref::<Error>().unwrap();
+        assert_eq!(&[("table".to_string(), "users".to_string())], inner.fields());
+        assert_eq!(
+            format!(" error: request failed{}\n field: request_id=42\n field: user=geralt\n cause: query failed\n field: table=users\n cause: cause", at(&err)),
+            format!("{:#}", err)
+        );
+    }
+
+    #[test]
+    fn test_with_metadata_from() {
+        initialize();
+        let other = Error::raw("other").with_field("request_id", "42").with_field("user", "geralt").with_code(2);
+        let err = Error::raw("foo").with_field("user", "ciri").with_metadata_from(&other);
+        assert_eq!(Some(2), err.code());
+        assert_eq!(Some(3), Error::raw("foo").with_code(3).with_metadata_from(&other).code());
+        assert_eq!(&[("user".to_string(), "ciri".to_string()), ("request_id".to_string(), "42".to_string())], err.fields());
+        assert_eq!(format!(" error: foo{}\n field: user=ciri\n field: request_id=42", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_to_line() {
+        initialize();
+        let err = Error::wrapr(TestError { msg: "cause 1".to_string(), inner: Some(Box::new(TestError { msg: "cause 2".to_string(), inner: Some(Box::new(TestError { msg: "cause 3".to_string(), inner: None })) })) }, "wrapped");
+        assert_eq!("wrapped: cause 1: cause 2: cause 3", err.to_line());
+        assert_eq!("oh no!", Error::raw("oh no!").to_line());
+    }
+
+    #[test]
+    fn test_summary() {
+        initialize();
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
+        assert_eq!("wrapped: cause", err.summary(80));
+        assert_eq!("wrapped: cause", err.summary(14));
+        assert_eq!("wrapped: c…", err.summary(11));
+        assert_eq!(11, err.summary(11).chars().count());
+        assert_eq!("", err.summary(0));
+    }
+
+    #[test]
+    fn test_io_kind() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(std::io::Error::from(std::io::ErrorKind::NotFound), "inner"), "outer");
+        assert_eq!(Some(std::io::ErrorKind::NotFound), err.io_kind());
+        assert_eq!(None, Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped").io_kind());
+    }
+
+    #[test]
+    fn test_debug_reversed() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::wrapr(Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "inner"), "outer");
+        let layers = |x: String| x.lines().filter(|x| x.starts_with(" error:")).map(String::from).collect::<Vec<String>>();
+        let normal = layers(format!("{:?}", err));
+        let reversed = layers(err.debug_reversed());
+        assert_eq!(vec![" error: witcher::Error: inner", " error: witcher::Error: outer"], normal);
+        assert_eq!(normal.into_iter().rev().collect::<Vec<String>>(), reversed);
+    }
+
+    #[test]
+    fn test_into_result() {
+        initialize();
+        let err = Error::raw("oh no!").with_code(2).into_result::<()>().unwrap_err();
+        assert_eq!("oh no!", err.to_string());
+        assert_eq!(Some(2), err.code());
+    }
+
+    // Location line written out in the alternate display for the given error
+    fn at(err: &Error) -> String {
+        let loc = err.location().unwrap();
+        format!("\n    at: {}:{}", loc.file(), loc.line())
+    }
+
+    fn frame(symbol: &str, lineno: u32) -> Frame {
+        Frame { symbol: symbol.to_string(), filename: "src/foo.rs".to_string(), lineno: Some(lineno), column: None }
+    }
+
+    #[test]
+    fn test_frames_parent_longer() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut inner = Error::raw("cause");
+        inner.meta.stack = vec![frame("foo::read", 20), frame("foo::main", 5)].into();
+        let mut err = Error::wrapr(inner, "wrapped");
+        err.meta.stack = vec![frame("foo::retry", 40), frame("foo::deep", 30), frame("foo::run", 10), frame("foo::main", 5)].into();
+
+        // The wrapping error has more frames than the one it wraps without underflowing
+        let output = format!("{:?}", err);
+        assert_eq!(1, output.matches("symbol: foo::main").count());
+        assert_eq!(1, output.matches("symbol: foo::read").count());
+        assert_eq!(1, output.matches("symbol: foo::retry").count());
+        let inner = err.source().unwrap().downcast_ref::<Error>().unwrap();
+        assert_eq!(vec![&frame("foo::read", 20)], inner.frames_for(Some(&err), false));
+
+        // Frames shared across a deeper chain are only shown once
+        let mut err = Error::wrapr(err, "outer");
+        err.meta.stack = vec![frame("foo::main", 5)].into();
+        let output = format!("{:?}", err);
+        assert_eq!(1, output.matches("symbol: foo::main").count());
+        assert_eq!(1, output.matches("symbol: foo::read").count());
+    }
+
+    #[test]
+    fn test_backtrace_string() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut err = Error::raw("oh no!");
+        err.meta.stack = vec![frame("foo::run", 20), frame("std::rt::lang_start", 10)].into();
+        crate::set_thread_color(Some(true));
+        assert_eq!("symbol: foo::run\n    at: src/foo.rs:20", err.backtrace_string());
+        crate::set_thread_color(None);
+
+        env::set_var(WITCHER_FULLSTACK, "1");
+        let output = err.backtrace_string();
+        env::remove_var(WITCHER_FULLSTACK);
+        assert_eq!("symbol: foo::run\n    at: src/foo.rs:20\nsymbol: std::rt::lang_start\n    at: src/foo.rs:10", output);
+        assert_eq!("", Error::raw("oh no!").backtrace_string());
+    }
+
+    #[test]
+    fn test_dim_dependency_frames() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut err = Error::raw("oh no!");
+        err.meta.stack = vec![frame("foo::run", 20), frame("std::rt::lang_start", 10)].into();
+        crate::set_thread_color(Some(true));
+        let output = format!("{:#?}", err);
+        crate::set_thread_color(None);
+        assert!(output.contains("symbol: \u{1b}[1;96mfoo::run\u{1b}[0m\n"));
+        assert!(output.contains("symbol: \u{1b}[2;96mstd::rt::lang_start\u{1b}[0m\n"));
+    }
+
+    #[test]
+    fn test_collapse_recursion() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut err = Error::raw("oh no!");
+        err.meta.stack = vec![frame("foo::recurse", 10), frame("foo::recurse", 11), frame("foo::recurse", 12), frame("foo::run", 20)].into();
+        env::set_var(WITCHER_COLLAPSE_RECURSION, "1");
+        let output = format!("{:?}", err);
+        env::remove_var(WITCHER_COLLAPSE_RECURSION);
+        assert_eq!(" error: witcher::Error: oh no!\nsymbol: foo::recurse (x3)\n    at: src/foo.rs:10\nsymbol: foo::run\n    at: src/foo.rs:20", output);
+    }
+
+    #[test]
+    fn test_with_context_chain() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        env::set_var(crate::WITCHER_BACKTRACE, "1");
+        let inner = Error::raw("cause");
+        env::remove_var(crate::WITCHER_BACKTRACE);
+        assert!(!inner.frames().is_empty());
+
+        let err = inner.with_context_chain(&["outer", "middle"]);
+        assert_eq!(" error: outer\n cause: middle\n cause: cause", format!("{:#}", err));
+        let chain: Vec<&Error> = err.chain().filter_map(|x| x.downcast_ref::<Error>()).collect();
+        assert_eq!(3, chain.len());
+        assert!(chain[0].frames().is_empty());
+        assert!(chain[1].frames().is_empty());
+        assert!(!chain[2].frames().is_empty());
+
+        let output = format!("{:?}", err);
+        assert_eq!(3, output.matches(" error: witcher::Error: ").count());
+        // Frames in `witcher::error` are filtered as dependencies so check the unfiltered form
+        let output = format!("{:#?}", err);
+        let lines: Vec<bool> = output.lines().map(|x| x.starts_with("symbol:") || x.starts_with("    at:")).collect();
+        assert_eq!(1, lines.windows(2).filter(|x| !x[0] && x[1]).count());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        initialize();
+        use std::process::ExitCode;
+        assert_eq!(ExitCode::FAILURE, Error::raw("oh no!").exit_code());
+        assert_eq!(ExitCode::from(2), Error::raw("oh no!").with_code(2).exit_code());
+        assert_eq!(ExitCode::from(255), Error::raw("oh no!").with_code(255).exit_code());
+        assert_eq!(ExitCode::from(255), Error::raw("oh no!").with_code(1000).exit_code());
+        assert_eq!(ExitCode::FAILURE, Error::raw("oh no!").with_code(-1).exit_code());
+        assert_eq!(ExitCode::FAILURE, Error::raw("oh no!").with_code(0).exit_code());
+    }
+
+    #[test]
+    fn test_render_into() {
+        initialize();
         let mut buf = String::new();
-        buf += &format!(" error: {}", self.msg().red());
+        let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
+        err.render_into(&mut buf, Format::Alternate);
+        assert_eq!(format!(" error: wrapped{}\n cause: cause", at(&err)), buf);
+        Error::raw("oh no!").render_into(&mut buf, Format::Normal);
+        assert_eq!("oh no!", buf);
+        Error::raw("oh no!").render_into(&mut buf, Format::Debug);
+        assert!(buf.starts_with(" error: witcher::Error: oh no!"));
+    }
 
-        // Traverse the whole chain
-        let mut source = if self.pass {
-            self.source().and_then(|x| x.source())
-        } else {
-            self.source()
+    #[test]
+    fn test_tags() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::wrapr(Error::raw("inner").with_tags(&["io"]), "outer").with_tags(&["db", "retryable"]);
+        assert_eq!(&["db".to_string(), "retryable".to_string()], err.tags());
+        let output = format!("{:?}", err);
+        assert!(output.contains(" error: witcher::Error: outer\n  tags: db, retryable\n"));
+        assert!(!output.contains("  tags: io"));
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let crumbs = vec!["opened config".to_string(), "parsed header".to_string()];
+        let err = Error::wrapr(Error::raw("inner"), "outer").with_breadcrumbs(crumbs.clone());
+        assert_eq!(&crumbs[..], err.breadcrumbs());
+        let output = format!("{:?}", err);
+        assert!(output.contains(" error: witcher::Error: outer\n breadcrumbs:\n   1. opened config\n   2. parsed header\n"));
+        assert!(Error::raw("oh no!").breadcrumbs().is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        initialize();
+        let new = |msg: &str, lineno: u32| {
+            let mut inner = Error::raw("cause");
+            inner.meta.stack = vec![frame("foo::run", lineno)].into();
+            Error::wrapr(inner, msg)
         };
-        while let Some(stderr) = source {
-            if !buf.ends_with('\n') {
-                buf += &"\n";
-            }
-            buf += &" cause: ".to_string();
-            match stderr.downcast_ref::<Error>() {
-                Some(err) => buf += &format!("{}", err.msg().red()),
-                _ => buf += &format!("{}", stderr.to_string().red()),
-            }
-            source = stderr.source();
-        }
-        write!(f, "{}", buf)
+        assert_eq!(new("first", 20).fingerprint(), new("second", 20).fingerprint());
+        assert_eq!("e8db38870bb8b6ef", new("first", 20).fingerprint());
+        assert_ne!(new("first", 20).fingerprint(), new("first", 21).fingerprint());
+        assert_ne!(new("first", 20).fingerprint(), Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "first").fingerprint());
+        assert_eq!("db-timeout", new("first", 20).with_fingerprint("db-timeout").fingerprint());
     }
-}
 
-// Unit tests
-// -------------------------------------------------------------------------------------------------
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
+    #[test]
+    #[cfg(feature = "process-info")]
+    fn test_process_info() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::raw("oh no!").with_process_info();
+        let output = format!("{:?}", err);
+        assert!(output.starts_with(&format!(" error: witcher::Error: oh no!\n   pid: {} host: ", std::process::id())));
+        assert!(!output.starts_with(&format!(" error: witcher::Error: oh no!\n   pid: {} host: \n", std::process::id())));
+    }
 
-    // Disable backtrace and colors
-    use std::sync::Once;
-    static INIT: Once = Once::new();
-    pub fn initialize() {
-        INIT.call_once(|| {
-            env::set_var(gory::TERM_COLOR, "0");
-            env::set_var("RUST_BACKTRACE", "0");
-        });
+    #[test]
+    fn test_capture_env() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        env::set_var("WITCHER_TEST_MODE", "fast");
+        env::set_var("WITCHER_TEST_TOKEN", "hunter2");
+        let err = Error::raw("oh no!").capture_env(&["WITCHER_TEST_MODE", "WITCHER_TEST_TOKEN", "WITCHER_TEST_UNSET"]);
+        env::remove_var("WITCHER_TEST_MODE");
+        env::remove_var("WITCHER_TEST_TOKEN");
+        let output = format!("{:?}", err);
+        assert!(output.starts_with(" error: witcher::Error: oh no!\n   env: WITCHER_TEST_MODE=fast\n   env: WITCHER_TEST_TOKEN=<redacted>\n"));
+        assert!(!output.contains("hunter2"));
+        assert!(!output.contains("WITCHER_TEST_UNSET"));
     }
 
-    struct TestError {
-        msg: String,
-        inner: Option<Box<TestError>>,
+    #[test]
+    fn test_positioned() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::positioned("unexpected token", 42, 3, 7);
+        assert_eq!(Some((42, 3, 7)), err.position());
+        assert_eq!("unexpected token at input 42:3:7", err.to_string());
+        assert!(format!("{:?}", err).starts_with(" error: witcher::Error: unexpected token at input 42:3:7\n"));
+        assert_eq!(None, Error::raw("oh no!").position());
     }
-    #[cfg(not(tarpaulin_include))]
-    impl Debug for TestError {
-        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-            write!(f, "{}", self.msg)
-        }
+
+    #[test]
+    fn test_count() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        assert_eq!(1, Error::raw("timeout").count());
+        assert_eq!("timeout", Error::raw("timeout").with_count(1).to_string());
+
+        let err = Error::raw("timeout").with_count(3);
+        assert_eq!(3, err.count());
+        assert_eq!("timeout (occurred 3 times)", err.to_string());
+        assert!(format!("{:?}", err).starts_with(" error: witcher::Error: timeout (occurred 3 times)\n"));
     }
-    impl Display for TestError {
-        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-            write!(f, "{}", self.msg)
+
+    #[test]
+    fn test_is_retryable() {
+        initialize();
+        assert!(Error::raw("busy").with_tags(&["retryable"]).is_retryable());
+        assert!(Error::wrapr(Error::raw("busy").with_tags(&["db", "retryable"]), "query failed").is_retryable());
+        assert!(Error::wrapr(std::io::Error::from(std::io::ErrorKind::TimedOut), "connect failed").is_retryable());
+        assert!(!Error::raw("oh no!").with_tags(&["db"]).is_retryable());
+        assert!(!Error::wrapr(std::io::Error::from(std::io::ErrorKind::NotFound), "open failed").is_retryable());
+    }
+
+    #[test]
+    fn test_tree() {
+        initialize();
+        let err = Error::wrapr(TestError { msg: "cause 1".to_string(), inner: Some(Box::new(TestError { msg: "cause 2".to_string(), inner: None })) }, "wrapped");
+        assert_eq!("wrapped\n  cause 1\n    cause 2", err.tree());
+
+        let err = Error::wrapr(
+            Error::aggregate("multiple failures", vec![Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "first"), Error::raw("second")]),
+            "top",
+        );
+        assert_eq!(2, err.source().unwrap().downcast_ref::<Error>().unwrap().errors().len());
+        assert_eq!("top\n  multiple failures\n  |- first\n  |    cause\n  `- second", err.tree());
+    }
+
+    #[test]
+    fn test_merge_contexts() {
+        initialize();
+        let primary = Error::wrapr(TestError { msg: "disk full".to_string(), inner: None }, "write cache");
+        let secondary = Error::raw("connection refused");
+        let err = Error::merge_contexts(primary, secondary, "sync failed");
+        assert_eq!(2, err.errors().len());
+        assert_eq!("sync failed\n|- write cache\n|    disk full\n`- connection refused", err.tree());
+
+        let _lock = ENV_LOCK.lock().unwrap();
+        assert_eq!(
+            " error: witcher::Error: sync failed\n\nbranch: 1\n error: witcher::Error: write cache\n cause: witcher::error::tests::TestError: disk full\n\nbranch: 2\n error: witcher::Error: connection refused\n",
+            format!("{:?}", err)
+        );
+        let (primary, secondary) = (&err.errors()[0], &err.errors()[1]);
+        assert_eq!(
+            format!(" error: sync failed{}\nbranch: 1\n error: write cache{}\n cause: disk full\nbranch: 2\n error: connection refused{}", at(&err), at(primary), at(secondary)),
+            format!("{:#}", err)
+        );
+    }
+
+    #[test]
+    fn test_payload() {
+        initialize();
+        #[derive(Debug, PartialEq)]
+        struct Request {
+            id: u32,
         }
+        let mut err = Error::raw("oh no!").with_payload(Request { id: 7 }).with_payload(42u8);
+        assert_eq!(Some(&Request { id: 7 }), err.payload::<Request>());
+        assert_eq!(None, err.take_payload::<String>());
+        assert_eq!(Some(Request { id: 7 }), err.take_payload::<Request>());
+        assert_eq!(None, err.take_payload::<Request>());
+        assert_eq!(Some(&42u8), err.payload::<u8>());
     }
-    impl StdError for TestError {
-        fn source(&self) -> Option<&(dyn StdError+'static)> {
-            match &self.inner {
-                Some(x) => Some(x as &dyn StdError),
-                None => None,
-            }
+
+    #[test]
+    fn test_kind() {
+        initialize();
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        enum AppError {
+            NotFound,
+            Timeout,
         }
+        let err = Error::raw("oh no!").with_kind(AppError::NotFound);
+        assert_eq!(Some(AppError::NotFound), err.kind::<AppError>());
+        assert_eq!(Some(AppError::Timeout), err.with_kind(AppError::Timeout).kind::<AppError>());
+        assert_eq!(None, Error::raw("oh no!").kind::<AppError>());
     }
 
-    // Test alte
     #[test]
-    fn test_output_levels() {
+    fn test_has_kind() {
         initialize();
+        #[derive(Clone, Copy)]
+        struct Timeout;
+        #[derive(Clone, Copy)]
+        struct NotFound;
+        let err = Error::wrapr(Error::wrapr(std::io::Error::other("oh no!"), "read failed").with_kind(Timeout), "request failed");
+        assert!(err.kind::<Timeout>().is_none());
+        assert!(err.has_kind::<Timeout>());
+        assert!(!err.has_kind::<NotFound>());
+        assert!(Error::raw("oh no!").with_kind(NotFound).has_kind::<NotFound>());
+        assert!(!Error::raw("oh no!").has_kind::<NotFound>());
+    }
 
-        // Test standard output
-        assert_eq!("wrapped", format!("{}", Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped")));
+    #[test]
+    fn test_wrap_owned() {
+        initialize();
+        use crate::Wrapper;
+        let msg = format!("wrapped {}", 1);
+        let ptr = msg.as_ptr();
+        let err = Err::<(), TestError>(TestError { msg: "cause".to_string(), inner: None }).wrap_owned(msg).unwrap_err();
+        assert_eq!(format!(" error: wrapped 1{}\n cause: cause", at(&err)), format!("{:#}", err));
+        assert_eq!(ptr, err.msg.as_ptr());
+    }
 
-        // Test alternate standard output
-        assert_eq!(" error: wrapped\n cause: cause", format!("{:#}", Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped")));
+    #[test]
+    fn test_with_inner() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::raw("wrapped").with_inner(TestError { msg: "cause".to_string(), inner: None });
+        assert_eq!(format!(" error: wrapped{}\n cause: cause", at(&err)), format!("{:#}", err));
+        assert_eq!(" error: witcher::Error: wrapped\n cause: witcher::error::tests::TestError: cause\n", format!("{:?}", err).split("symbol").next().unwrap());
+    }
 
+    #[test]
+    fn test_thread_color() {
+        initialize();
         let err = Error::wrapr(TestError { msg: "cause".to_string(), inner: None }, "wrapped");
-        assert_eq!(" error: witcher::Error: wrapped\n cause: witcher::error::tests::TestError: cause\n", format!("{:?}", err).split("symbol").next().unwrap());
-        let err = Error::wrapr(
-            TestError {
-                msg: "cause".to_string(),
-                inner: Some(Box::new(TestError { msg: "cause2".to_string(), inner: None })),
-            },
-            "wrapped",
-        );
-        assert_eq!(" error: witcher::Error: wrapped\n cause: witcher::error::tests::TestError: cause\n cause: std::error::Error: cause2\n", format!("{:#?}", err).split("symbol").next().unwrap());
+        crate::set_thread_color(Some(true));
+        assert_eq!(format!(" error: \x1b[1;91mwrapped\x1b[0m{}\n cause: \x1b[1;91mcause\x1b[0m", at(&err)), format!("{:#}", err));
+        crate::set_thread_color(None);
+        assert_eq!(format!(" error: wrapped{}\n cause: cause", at(&err)), format!("{:#}", err));
     }
 
     #[test]
-    fn test_chained_cause() {
+    fn test_downcast_ref_or() {
+        initialize();
+        let default = std::io::Error::other("default");
+        let err = Error::pass::<(), _>(std::io::Error::other("actual")).unwrap_err();
+        assert_eq!("actual", err.downcast_ref_or(&default).to_string());
+        assert_eq!("default", Error::raw("oh no!").downcast_ref_or(&default).to_string());
+    }
+
+    #[test]
+    fn test_frame_indent() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut err = Error::raw("oh no!");
+        err.meta.stack = vec![frame("foo::run", 20)].into();
+        assert_eq!(" error: witcher::Error: oh no!\nsymbol: foo::run\n    at: src/foo.rs:20", format!("{:?}", err));
+        env::set_var(WITCHER_FRAME_INDENT, "2");
+        let output = format!("{:?}", err);
+        env::remove_var(WITCHER_FRAME_INDENT);
+        assert_eq!(" error: witcher::Error: oh no!\nsymbol: foo::run\n  at: src/foo.rs:20", output);
+    }
+
+    #[test]
+    fn test_deferred_symbols() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        env::set_var(crate::WITCHER_BACKTRACE, "1");
+        let err = Error::raw("oh no!");
+        env::remove_var(crate::WITCHER_BACKTRACE);
+
+        // Symbols are only resolved the first time the frames are written out
+        assert!(!err.meta.stack.is_resolved());
+        assert!(format!("{:#?}", err).contains("symbol: "));
+        assert!(err.meta.stack.is_resolved());
+        assert!(Error::raw("oh no!").meta.stack.frames().is_empty());
+    }
+
+    #[test]
+    fn test_raw_backtrace() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        assert!(Error::raw("oh no!").raw_backtrace().is_none());
+        env::set_var(crate::WITCHER_RAW_BACKTRACE, "1");
+        assert!(Error::raw("oh no!").raw_backtrace().is_none());
+        env::set_var("RUST_BACKTRACE", "1");
+        let err = Error::raw("oh no!");
+        env::set_var(crate::WITCHER_FRAME_LIMIT, "3");
+        let limited = Error::raw("oh no!");
+        env::remove_var(crate::WITCHER_FRAME_LIMIT);
+        env::set_var("RUST_BACKTRACE", "0");
+        env::remove_var(crate::WITCHER_RAW_BACKTRACE);
+        assert!(!err.raw_backtrace().unwrap().frames().is_empty());
+
+        // The frames are built from the same single capture as the retained backtrace
+        let symbols = err.raw_backtrace().unwrap().frames().iter().flat_map(|x| x.symbols()).count();
+        assert_eq!(symbols, err.frames().len());
+        assert_eq!(3, limited.raw_backtrace().unwrap().frames().len());
+        assert!(err.canonicalize().raw_backtrace().is_none());
+    }
+
+    #[test]
+    fn test_max_frames() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut err = Error::raw("oh no!");
+        err.meta.stack = vec![frame("foo::fail", 30), frame("foo::run", 20), frame("foo::main", 10)].into();
+        env::set_var(WITCHER_MAX_FRAMES, "1");
+        let top = format!("{:?}", err);
+        env::set_var(WITCHER_MAX_FRAMES_FROM, "bottom");
+        let bottom = format!("{:?}", err);
+        env::set_var(WITCHER_MAX_FRAMES, "5");
+        let all = format!("{:?}", err);
+        env::remove_var(WITCHER_MAX_FRAMES);
+        env::remove_var(WITCHER_MAX_FRAMES_FROM);
+        assert_eq!(" error: witcher::Error: oh no!\nsymbol: foo::fail\n    at: src/foo.rs:30", top);
+        assert_eq!(" error: witcher::Error: oh no!\nsymbol: foo::main\n    at: src/foo.rs:10", bottom);
+        assert_eq!(3, all.matches("symbol:").count());
+    }
+
+    #[test]
+    fn test_is_same_origin() {
+        initialize();
+        let mut err1 = Error::raw("first");
+        err1.meta.stack = vec![frame("foo::run", 20), frame("foo::main", 10)].into();
+        let mut err2 = Error::raw("second");
+        err2.meta.stack = vec![frame("foo::run", 20)].into();
+        let mut err3 = Error::raw("first");
+        err3.meta.stack = vec![frame("foo::run", 21), frame("foo::main", 10)].into();
+        assert!(err1.is_same_origin(&err2));
+        assert!(!err1.is_same_origin(&err3));
+        err3.meta.stack.frames_mut().clear();
+        assert!(!err1.is_same_origin(&err3));
+    }
+
+    #[test]
+    fn test_group_by_type() {
         initialize();
         let err = TestError {
             msg: "cause 1".to_string(),
@@ -415,23 +2716,185 @@ mod tests {
                 inner: Some(Box::new(TestError { msg: "cause 3".to_string(), inner: None })),
             })),
         };
+        let err = Error::wrapr(Error::wrapr(err, "inner"), "outer");
+        assert_eq!(
+            vec![("witcher::Error".to_string(), 2), ("witcher::error::tests::TestError".to_string(), 1), ("std::error::Error".to_string(), 2)],
+            err.group_by_type()
+        );
+    }
 
-        assert_eq!(" error: wrapped\n cause: cause 1\n cause: cause 2\n cause: cause 3", format!("{:#}", Error::wrapr(err, "wrapped")));
+    #[test]
+    fn test_severity() {
+        initialize();
+        assert_eq!(Severity::Error, Error::raw("oh no!").severity());
+        assert_eq!(Severity::Warning, Error::raw("oh no!").with_severity(Severity::Warning).severity());
     }
 
     #[test]
-    fn test_ext_and_last() {
+    fn test_demote_promote() {
         initialize();
-        let err = TestError {
-            msg: "cause 1".to_string(),
-            inner: Some(Box::new(TestError {
-                msg: "cause 2".to_string(),
-                inner: Some(Box::new(TestError { msg: "cause 3".to_string(), inner: None })),
-            })),
+        let err = Error::raw("oh no!").demote();
+        assert_eq!(Severity::Warning, err.severity());
+        assert_eq!(format!("  warn: oh no!{}", at(&err)), format!("{:#}", err));
+        let err = err.demote();
+        assert_eq!(Severity::Info, err.severity());
+        assert_eq!(format!("  info: oh no!{}", at(&err)), format!("{:#}", err));
+        let err = err.demote();
+        assert_eq!(Severity::Info, err.severity());
+        let err = err.promote();
+        assert_eq!(Severity::Warning, err.severity());
+        let err = err.promote();
+        assert_eq!(Severity::Error, err.severity());
+        assert_eq!(format!(" error: oh no!{}", at(&err)), format!("{:#}", err));
+        assert_eq!(Severity::Error, err.promote().severity());
+    }
+
+    #[cfg(feature = "syslog")]
+    #[test]
+    fn test_to_syslog() {
+        initialize();
+        let err = Error::wrapr(Error::raw("cause").with_field("user", "geralt of rivia"), "wrapped").with_field("request_id", "42");
+        assert_eq!(format!("<11>1 - - - {} - - wrapped: cause request_id=42 user=\"geralt of rivia\"", std::process::id()), err.to_syslog());
+        assert!(Error::raw("oh no!").with_severity(Severity::Warning).to_syslog().starts_with("<12>1 "));
+        assert!(Error::raw("oh no!").with_severity(Severity::Info).to_syslog().starts_with("<14>1 "));
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn test_otel_attributes() {
+        initialize();
+        let mut cause = Error::wrapr(std::io::Error::other("oh no!"), "read failed");
+        cause.meta.stack = vec![frame("foo::read", 20), frame("foo::run", 10)].into();
+        let err = Error::wrapr(cause, "request failed");
+        assert_eq!(
+            vec![
+                ("exception.type", "std::io::error::Error".to_string()),
+                ("exception.message", "request failed: read failed: oh no!".to_string()),
+                ("exception.stacktrace", "foo::read\n    at src/foo.rs:20\nfoo::run\n    at src/foo.rs:10".to_string())
+            ],
+            err.otel_attributes()
+        );
+        assert_eq!(("exception.type", "witcher::Error".to_string()), Error::raw("oh no!").otel_attributes()[0]);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_record() {
+        use std::sync::{Arc, Mutex};
+        use tracing::{
+            field::{Field, Visit},
+            span, Metadata, Subscriber,
         };
-        assert_eq!("foo", Error::wrapr(TestError { msg: "cause 1".to_string(), inner: None }, "foo").to_string());
-        assert_eq!("cause 1", Error::wrapr(TestError { msg: "cause 1".to_string(), inner: None }, "foo").ext().to_string());
-        assert_eq!("cause 3", Error::wrapr(err, "foo").last().to_string());
+
+        // Subscriber capturing the fields of each event
+        type Event = Vec<(String, String)>;
+        struct Capture(Arc<Mutex<Vec<Event>>>);
+        struct Fields(Event);
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0.push((field.name().to_string(), format!("{:?}", value)));
+            }
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.push((field.name().to_string(), value.to_string()));
+            }
+        }
+        impl Subscriber for Capture {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, event: &tracing::Event<'_>) {
+                let mut fields = Fields(Vec::new());
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+            }
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut err = Error::wrapr(Error::wrapr(std::io::Error::other("cause 2"), "cause 1"), "wrapped");
+        err.meta.stack = vec![frame("foo::run", 20)].into();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(Capture(events.clone()), || {
+            err.record();
+            env::set_var(WITCHER_FULLSTACK, "1");
+            err.record();
+            env::remove_var(WITCHER_FULLSTACK);
+        });
+        let field = |event: &Event, name: &str| event.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+        let events = events.lock().unwrap();
+        assert_eq!(Some("wrapped".to_string()), field(&events[0], "message"));
+        assert_eq!(Some("witcher::Error".to_string()), field(&events[0], "type_name"));
+        assert_eq!(Some("wrapped: cause 1: cause 2".to_string()), field(&events[0], "chain"));
+        assert_eq!(None, field(&events[0], "backtrace"));
+        assert_eq!(Some("symbol: foo::run\n    at: src/foo.rs:20".to_string()), field(&events[1], "backtrace"));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        initialize();
+        let err1 = Error::wrapr(Error::raw("cause").with_code(2), "wrapped");
+        let mut err2 = Error::wrapr(Error::raw("cause").with_code(2), "wrapped");
+        err2.meta.stack = vec![frame("foo::run", 20)].into();
+        assert!(err1 != err2);
+        let (err1, err2) = (err1.canonicalize(), err2.canonicalize());
+        assert!(err1 == err2);
+        assert!(err1.frames().is_empty());
+        assert!(err1.source().unwrap().downcast_ref::<Error>().unwrap().frames().is_empty());
+        assert_eq!(None, err1.created_at());
+        assert_eq!(None, err1.thread_id());
+        assert_eq!(None, err1.source().unwrap().downcast_ref::<Error>().unwrap().created_at());
+        assert!(err1 != Error::wrapr(Error::raw("cause").with_code(3), "wrapped").canonicalize());
+
+        let cwd = env::current_dir().unwrap();
+        let err1 = Error::raw("unexpected token").with_source_location(&cwd.join("config.toml").display().to_string(), 12);
+        let err2 = Error::raw("unexpected token").with_source_location("config.toml", 12);
+        assert!(err1 != err2);
+        let (err1, err2) = (err1.canonicalize(), err2.canonicalize());
+        assert_eq!(Some(("config.toml", 12)), err1.source_location());
+        assert!(err1 == err2);
+    }
+
+    #[test]
+    fn test_with_source_location() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let err = Error::raw("unexpected token").with_source_location("config.toml", 12);
+        assert_eq!(Some(("config.toml", 12)), err.source_location());
+        assert!(format!("{:?}", err).starts_with(" error: witcher::Error: unexpected token\norigin: config.toml:12\n"));
+    }
+
+    #[test]
+    fn test_with_backtrace_from() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut original = Error::raw("original");
+        original.meta.stack = vec![frame("foo::parse", 42)].into();
+        let err = Error::raw("reconstructed").with_backtrace_from(&original);
+        assert_eq!(original.frames(), err.frames());
+        assert_eq!(" error: witcher::Error: reconstructed\nsymbol: foo::parse\n    at: src/foo.rs:42", format!("{:?}", err));
+    }
+
+    #[test]
+    fn test_map_frames() {
+        initialize();
+        let mut inner = Error::raw("cause");
+        inner.meta.stack = vec![frame("foo::inner", 10)].into();
+        let mut err = Error::wrapr(inner, "wrapped");
+        err.meta.stack = vec![frame("foo::outer", 20)].into();
+
+        let err = err.map_frames(|mut x| {
+            x.filename = x.filename.replace("src/", "redacted/");
+            x
+        });
+        assert_eq!("redacted/foo.rs", err.frames()[0].filename);
+        assert_eq!("redacted/foo.rs", err.source().unwrap().downcast_ref::<Error>().unwrap().frames()[0].filename);
     }
 
     #[test]
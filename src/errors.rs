@@ -0,0 +1,96 @@
+use crate::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// `Errors` is a distinct collection of independent errors that can be written out as a
+/// numbered list using the same formatting options as a single `Error`.
+///
+/// Unlike `Error::aggregate` the errors are kept as a plain list without a summary message.
+///
+/// ### Examples
+/// ```
+/// use witcher::prelude::*;
+/// let errs = witcher::Errors::from(vec![Error::raw("first"), Error::raw("second")]);
+/// assert_eq!("1. first\n2. second", errs.to_string());
+/// ```
+#[derive(Default)]
+pub struct Errors(pub Vec<Error>);
+
+impl Errors {
+    // Write out each error numbered using the given formatter function
+    fn write(&self, f: &mut Formatter<'_>, sep: &str, fmt: impl Fn(&Error, &mut Formatter<'_>) -> fmt::Result) -> fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{}", sep)?;
+            }
+            write!(f, "{}. ", i + 1)?;
+            fmt(err, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Error>> for Errors {
+    fn from(errors: Vec<Error>) -> Self {
+        Errors(errors)
+    }
+}
+
+// Write out the errors numbered one per line forwarding the alternate flag
+impl Display for Errors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            self.write(f, "\n", |err, f| write!(f, "{:#}", err))
+        } else {
+            self.write(f, "\n", |err, f| write!(f, "{}", err))
+        }
+    }
+}
+
+// Write out the errors numbered separated by a blank line forwarding the alternate flag.
+// The invoked command line is written out once up front rather than for every error.
+impl Debug for Errors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        crate::process::write_command(f)?;
+        let fullstack = f.alternate();
+        self.write(f, "\n\n", |err, f| err.write_debug(f, fullstack, false, false))
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ENV_LOCK;
+
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+    pub fn initialize() {
+        INIT.call_once(|| {
+            std::env::set_var(gory::TERM_COLOR, "0");
+            std::env::set_var("RUST_BACKTRACE", "0");
+        });
+    }
+
+    // Location line written out in the alternate display for the given error
+    fn at(err: &Error) -> String {
+        let loc = err.location().unwrap();
+        format!("\n    at: {}:{}", loc.file(), loc.line())
+    }
+
+    #[test]
+    fn test_errors() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let errs: Errors = vec![Error::raw("first"), Error::wrapr(Error::raw("cause"), "second"), Error::raw("third")].into();
+        assert_eq!(3, errs.0.len());
+        assert_eq!("1. first\n2. second\n3. third", errs.to_string());
+        let (first, second, third) = (at(&errs.0[0]), at(&errs.0[1]), at(&errs.0[2]));
+        assert_eq!(format!("1.  error: first{}\n2.  error: second{}\n cause: cause\n3.  error: third{}", first, second, third), format!("{:#}", errs));
+        let output = format!("{:?}", errs);
+        assert!(output.starts_with("1.  error: witcher::Error: first"));
+        assert!(output.contains("\n\n2.  error: witcher::Error: cause"));
+        assert!(output.contains("\n\n3.  error: witcher::Error: third"));
+        assert_eq!("", Errors::default().to_string());
+    }
+}
@@ -1,12 +1,56 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+extern crate alloc;
+
+#[cfg(feature = "std")]
 mod backtrace;
+#[cfg(feature = "std")]
 mod error;
+#[cfg(feature = "std")]
+mod errors;
+#[cfg(not(feature = "std"))]
+mod nostd;
+#[cfg(feature = "std")]
+mod process;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod term;
+#[cfg(feature = "std")]
 mod wrapper;
+#[cfg(not(feature = "std"))]
+use core::error::Error as StdError;
+#[cfg(feature = "std")]
 use std::error::Error as StdError;
 
-pub use crate::{error::Error, wrapper::Wrapper};
+/// Re-export of the color type used to define a `Theme`
+#[cfg(feature = "std")]
+pub use gory::Color;
+
+#[cfg(feature = "std")]
+pub use crate::{
+    backtrace::{add_filter_contains, add_filter_prefix, Frame, WITCHER_BACKTRACE, WITCHER_FRAME_LIMIT, WITCHER_RAW_BACKTRACE},
+    error::{Error, ErrorBuilder, Format, Severity, WITCHER_COLLAPSE_RECURSION, WITCHER_FRAME_INDENT, WITCHER_FULLSTACK, WITCHER_MAX_FRAMES, WITCHER_MAX_FRAMES_FROM, WITCHER_TIMESTAMP},
+    errors::Errors,
+    process::capture_argv,
+    retry::RetryPolicy,
+    term::{set_theme, set_thread_color, term_width, Theme},
+    wrapper::{AssertWrapper, BoolWrapper, OptionWrapper, Wrapper},
+};
+
+// Without `std` only the minimal message chaining core is available
+#[cfg(not(feature = "std"))]
+pub use crate::nostd::Error;
+
+// Used by the exported macros so they work in `no_std` crates without `format!` in scope
+#[doc(hidden)]
+pub use alloc::format as __format;
+
+// Serialize tests across modules changing or depending on environment variables
+#[cfg(all(test, feature = "std"))]
+static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
 /// `Result<T>` is a simplified return type to use throughout your application.
-pub type Result<T, E=Error> = std::result::Result<T, E>;
+pub type Result<T, E=Error> = core::result::Result<T, E>;
 
 /// Import all essential symbols in a simple consumable way
 ///
@@ -15,8 +59,10 @@ pub type Result<T, E=Error> = std::result::Result<T, E>;
 /// use witcher::prelude::*;
 /// ```
 pub mod prelude {
-    pub use super::{bail, err, match_err, wrap, Error, Result, Wrapper};
-    pub use std::any::TypeId;
+    pub use super::{bail, err, tryb, wrap, Error, Result};
+    #[cfg(feature = "std")]
+    pub use super::{match_err, BoolWrapper, OptionWrapper, RetryPolicy, Wrapper, WITCHER_BACKTRACE};
+    pub use core::any::TypeId;
 }
 
 /// Bail early from a function with an `Error`.
@@ -25,15 +71,28 @@ pub mod prelude {
 /// a user to return immediately with an error. Using `bail!("oh no!")` is the same thing as
 /// if you were to use `return Error::new("oh no!")` or `return Err(Error::raw("oh no!")`.
 ///
-/// It also provides a variation to allow for format!() type formatting.
+/// It also provides a variation to allow for format!() type formatting and variations taking
+/// an existing error separated by `;` to wrap with the message i.e. `return Error::wrap(err, msg)`.
 ///
 /// ### Examples
 /// ```rust,ignore
 /// bail!("oh no!");
 /// bail!("foo: {}", "oh no!");
+/// bail!(err; "oh no!");
+/// bail!(err; "foo: {}", "oh no!");
 /// ```
 #[macro_export]
 macro_rules! bail {
+    // Wrap an existing error with a simple message
+    ($err:expr; $msg:expr) => {
+        return $crate::Error::wrap($err, $msg);
+    };
+
+    // Wrap an existing error with format! style formatting
+    ($err:expr; $fmt:expr, $($arg:tt)*) => {
+        return $crate::Error::wrap($err, &$crate::__format!($fmt, $($arg)*));
+    };
+
     // Simple message
     ($msg:expr) => {
         return $crate::Error::new($msg);
@@ -41,7 +100,7 @@ macro_rules! bail {
 
     // format! style formatting
     ($fmt:expr, $($arg:tt)*) => {
-        return $crate::Error::new(&format!($fmt, $($arg)*));
+        return $crate::Error::new(&$crate::__format!($fmt, $($arg)*));
     };
 }
 
@@ -59,12 +118,12 @@ macro_rules! bail {
 macro_rules! err {
     // Simple message
     ($msg:expr) => {
-        $crate::Error::raw($msg);
+        $crate::Error::raw($msg)
     };
 
     // format! style formatting
     ($fmt:expr, $($arg:tt)*) => {
-        $crate::Error::raw(&format!($fmt, $($arg)*));
+        $crate::Error::raw(&$crate::__format!($fmt, $($arg)*))
     };
 }
 
@@ -74,7 +133,7 @@ macro_rules! err {
 ///
 /// ### Examples
 /// ```rust,ignore
-/// wrap!(std::io::Error::new(std::io::ErrorKind::Other, "oh no!"), "wrapper msg");
+/// wrap!(std::io::Error::other("oh no!"), "wrapper msg");
 /// ```
 #[macro_export]
 macro_rules! wrap {
@@ -85,7 +144,37 @@ macro_rules! wrap {
 
     // format! style formatting
     ($err:expr, $fmt:expr, $($arg:tt)*) => {
-        return $crate::Error::wrap($err, &format!($fmt, $($arg)*));
+        return $crate::Error::wrap($err, &$crate::__format!($fmt, $($arg)*));
+    };
+}
+
+/// `tryb!` behaves like `?` but always wraps the error with the given message
+///
+/// The error is wrapped at the call site capturing the location and backtrace there while on
+/// success the value is passed through. It also provides a variation to allow for format!()
+/// type formatting.
+///
+/// ### Examples
+/// ```rust,ignore
+/// let config = tryb!(std::fs::read_to_string("config"), "reading config");
+/// let config = tryb!(std::fs::read_to_string(path), "reading config: {}", path);
+/// ```
+#[macro_export]
+macro_rules! tryb {
+    // Simple message
+    ($result:expr, $msg:expr) => {
+        match $result {
+            Ok(val) => val,
+            Err(err) => return $crate::Error::wrap(err, $msg),
+        }
+    };
+
+    // format! style formatting
+    ($result:expr, $fmt:expr, $($arg:tt)*) => {
+        match $result {
+            Ok(val) => val,
+            Err(err) => return $crate::Error::wrap(err, &$crate::__format!($fmt, $($arg)*)),
+        }
     };
 }
 
@@ -93,32 +182,110 @@ macro_rules! wrap {
 /// This only works with errors implementing the `std::error::Error` trait as it makes use of
 /// the standard `is` and `downcast_ref` implementations.
 ///
+/// An arm of the form `category(kind) => expr` matches when the error is an `Error` tagged
+/// with the given user defined kind via `Error::with_kind`.
+///
+/// A type arm may take an `if` guard e.g. `x: io::Error if x.kind() == NotFound => expr` which
+/// falls through to the following arms when the guard fails. Guarded types must be given as a
+/// plain path e.g. `io::Error` without generic arguments.
+///
+/// The `match_err!(chain err, { ... })` form tries each arm in order against every error in
+/// the chain from the outermost to the innermost error before moving on to the next arm. The
+/// first arm matching any error in the chain is run with the matching error bound.
+///
 /// ### Examples
 /// ```rust
 /// use witcher::prelude::*;
-/// let err = std::io::Error::new(std::io::ErrorKind::Other, "oh no!");
+/// let err = std::io::Error::other("oh no!");
 /// let res = match_err!(&err, {
 ///     _x: std::io::Error => true,
 ///     _ => false
 /// });
 /// assert!(res);
+///
+/// let err = Error::wrapr(std::io::Error::other("oh no!"), "wrapped");
+/// let res = match_err!(chain &err, {
+///     x: std::io::Error => x.to_string(),
+///     _ => "no match".to_string()
+/// });
+/// assert_eq!("oh no!", res);
 /// ```
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! match_err {
-    ($err:expr, { $($var:ident : $kind:ty => $arm:expr),*, _ => $default:expr }) => (
-        $(
-            if ($err as &(dyn std::error::Error + 'static)).is::<$kind>() {
-                let $var = ($err as &(dyn std::error::Error + 'static)).downcast_ref::<$kind>().unwrap();
-                $arm
-            } else
-        )*
-        {
-            $default
+    (chain $err:expr, { $($arms:tt)* }) => (
+        $crate::match_err!(@chain $err, $($arms)*)
+    );
+
+    ($err:expr, { $($arms:tt)* }) => (
+        $crate::match_err!(@arm $err, $($arms)*)
+    );
+
+    // Chain default arm
+    (@chain $err:expr, _ => $default:expr $(,)?) => (
+        $default
+    );
+
+    // Chain category arm matching any error in the chain tagged with the given kind
+    (@chain $err:expr, category($kind:expr) => $arm:expr, $($rest:tt)*) => (
+        if std::iter::successors(Some($err as &(dyn std::error::Error + 'static)), |&x| x.source()).any(|x| x.downcast_ref::<$crate::Error>().is_some_and(|x| x.is_kind($kind))) {
+            $arm
+        } else {
+            $crate::match_err!(@chain $err, $($rest)*)
+        }
+    );
+
+    // Chain type arm matching the first error in the chain of the given type the guard holds for
+    (@chain $err:expr, $var:ident : $($kind:ident)::+ if $guard:expr => $arm:expr, $($rest:tt)*) => (
+        match std::iter::successors(Some($err as &(dyn std::error::Error + 'static)), |&x| x.source()).filter_map(|x| x.downcast_ref::<$($kind)::+>()).find(|&$var| $guard) {
+            #[allow(unused_variables)]
+            Some($var) => $arm,
+            None => $crate::match_err!(@chain $err, $($rest)*),
+        }
+    );
+
+    // Chain type arm matching the first error in the chain of the given type
+    (@chain $err:expr, $var:ident : $kind:ty => $arm:expr, $($rest:tt)*) => (
+        match std::iter::successors(Some($err as &(dyn std::error::Error + 'static)), |&x| x.source()).find_map(|x| x.downcast_ref::<$kind>()) {
+            Some($var) => $arm,
+            None => $crate::match_err!(@chain $err, $($rest)*),
+        }
+    );
+
+    // Default arm
+    (@arm $err:expr, _ => $default:expr $(,)?) => (
+        $default
+    );
+
+    // Category arm matching errors tagged with the given kind
+    (@arm $err:expr, category($kind:expr) => $arm:expr, $($rest:tt)*) => (
+        if ($err as &(dyn std::error::Error + 'static)).downcast_ref::<$crate::Error>().is_some_and(|x| x.is_kind($kind)) {
+            $arm
+        } else {
+            $crate::match_err!(@arm $err, $($rest)*)
         }
-    )
+    );
+
+    // Type arm matching errors of the given type only when the guard holds
+    (@arm $err:expr, $var:ident : $($kind:ident)::+ if $guard:expr => $arm:expr, $($rest:tt)*) => (
+        match ($err as &(dyn std::error::Error + 'static)).downcast_ref::<$($kind)::+>() {
+            Some($var) if $guard => $arm,
+            _ => $crate::match_err!(@arm $err, $($rest)*),
+        }
+    );
+
+    // Type arm matching errors of the given type
+    (@arm $err:expr, $var:ident : $kind:ty => $arm:expr, $($rest:tt)*) => (
+        if ($err as &(dyn std::error::Error + 'static)).is::<$kind>() {
+            let $var = ($err as &(dyn std::error::Error + 'static)).downcast_ref::<$kind>().unwrap();
+            $arm
+        } else {
+            $crate::match_err!(@arm $err, $($rest)*)
+        }
+    );
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use std::{env, fmt, io};
@@ -159,6 +326,20 @@ mod tests {
         bail!("foo: {}", "oh no!");
     }
 
+    fn bail_wrap_simple() -> Result<()> {
+        bail!(io::Error::new(io::ErrorKind::NotFound, "oh no!"); "bail_wrap");
+    }
+
+    fn bail_wrap_formatted() -> Result<()> {
+        bail!(io::Error::new(io::ErrorKind::NotFound, "oh no!"); "foo: {}", "bail_wrap");
+    }
+
+    // Location line written out in the alternate display for the given error
+    fn at(err: &Error) -> String {
+        let loc = err.location().unwrap();
+        format!("\n    at: {}:{}", loc.file(), loc.line())
+    }
+
     fn wrap_simple() -> Result<()> {
         wrap!(io::Error::new(io::ErrorKind::NotFound, "oh no!"), "simple_wrap");
     }
@@ -172,6 +353,13 @@ mod tests {
         initialize();
         assert_eq!("oh no!", bail_simple().unwrap_err().to_string());
         assert_eq!("foo: oh no!", bail_formatted().unwrap_err().to_string());
+
+        let err = bail_wrap_simple().unwrap_err();
+        assert_eq!("bail_wrap", err.to_string());
+        assert_eq!(io::ErrorKind::NotFound, err.ext().downcast_ref::<io::Error>().unwrap().kind());
+        let err = bail_wrap_formatted().unwrap_err();
+        assert_eq!(format!(" error: foo: bail_wrap{}\n cause: oh no!", at(&err)), format!("{:#}", err));
+        assert!(err.chain().any(|x| x.is::<io::Error>()));
     }
 
     #[test]
@@ -185,15 +373,40 @@ mod tests {
     fn test_wrap() {
         initialize();
         assert_eq!("simple_wrap", format!("{}", wrap_simple().unwrap_err()));
-        assert_eq!(" error: simple_wrap\n cause: oh no!", format!("{:#}", wrap_simple().unwrap_err()));
+        let err = wrap_simple().unwrap_err();
+        assert_eq!(format!(" error: simple_wrap{}\n cause: oh no!", at(&err)), format!("{:#}", err));
         assert_eq!("foo: simple_wrap", wrap_formatted().unwrap_err().to_string());
-        assert_eq!(" error: foo: simple_wrap\n cause: oh no!", format!("{:#}", wrap_formatted().unwrap_err()));
+        let err = wrap_formatted().unwrap_err();
+        assert_eq!(format!(" error: foo: simple_wrap{}\n cause: oh no!", at(&err)), format!("{:#}", err));
+    }
+
+    fn tryb_read(fail: bool) -> Result<i32> {
+        let val = tryb!(if fail { Err(io::Error::other("oh no!")) } else { Ok(42) }, "reading config");
+        Ok(val + 1)
+    }
+
+    fn tryb_formatted() -> Result<i32> {
+        let val = tryb!(Err::<i32, _>(io::Error::other("oh no!")), "reading {}", "config");
+        Ok(val)
+    }
+
+    #[test]
+    fn test_tryb() {
+        initialize();
+        assert_eq!(43, tryb_read(false).unwrap());
+        let line = line!() - 13;
+        let err = tryb_read(true).unwrap_err();
+        assert_eq!("reading config", err.to_string());
+        assert!(err.source().unwrap().is::<io::Error>());
+        assert_eq!(line, err.location().unwrap().line());
+        assert_eq!(format!(" error: reading config{}\n cause: oh no!", at(&err)), format!("{:#}", err));
+        assert_eq!("reading config", tryb_formatted().unwrap_err().to_string());
     }
 
     #[test]
     fn test_single() {
         initialize();
-        let err = io::Error::new(std::io::ErrorKind::Other, "oh no!");
+        let err = io::Error::other("oh no!");
         let res = match_err!(&err, {
             _x: io::Error => true,
             _ => false
@@ -207,7 +420,7 @@ mod tests {
         let errors: Vec<Box<dyn std::error::Error>> = vec![
             Box::new(TestError1("test1".to_string())),
             Box::new(TestError2("test2".to_string())),
-            Box::new(io::Error::new(std::io::ErrorKind::Other, "test3")),
+            Box::new(io::Error::other("test3")),
         ];
 
         let mut buf = String::new();
@@ -222,4 +435,79 @@ mod tests {
         }
         assert_eq!("TestError1: test1\nTestError2: test2\nio::Error: test3\n", buf);
     }
+
+    #[test]
+    fn test_match_err_guard() {
+        initialize();
+        let route = |err: &io::Error| {
+            match_err!(err, {
+                x: io::Error if x.kind() == io::ErrorKind::NotFound => "not found",
+                x: io::Error if x.kind() == io::ErrorKind::PermissionDenied => "denied",
+                _x: TestError1 => "test1",
+                _ => "other"
+            })
+        };
+        assert_eq!("not found", route(&io::Error::from(io::ErrorKind::NotFound)));
+        assert_eq!("denied", route(&io::Error::from(io::ErrorKind::PermissionDenied)));
+        assert_eq!("other", route(&io::Error::other("oh no!")));
+
+        // Failed guards fall through to the following arms rather than the default
+        let err = io::Error::other("oh no!");
+        let res = match_err!(&err, {
+            x: io::Error if x.kind() == io::ErrorKind::NotFound => "not found",
+            _x: io::Error => "io",
+            _ => "other"
+        });
+        assert_eq!("io", res);
+    }
+
+    #[test]
+    fn test_match_err_chain() {
+        initialize();
+        let err = Error::wrapr(Error::wrapr(io::Error::from(io::ErrorKind::NotFound), "read failed"), "request failed");
+
+        // Matches the deep io::Error even though the outer error is an `Error`
+        let res = match_err!(chain &err, {
+            x: io::Error => format!("io: {}", x.kind()),
+            x: Error => format!("witcher: {}", x),
+            _ => "other".to_string()
+        });
+        assert_eq!("io: entity not found", res);
+
+        // Arms are tried in order against the whole chain from the outermost error
+        let res = match_err!(chain &err, {
+            x: Error => format!("witcher: {}", x),
+            _x: io::Error => "io".to_string(),
+            _ => "other".to_string()
+        });
+        assert_eq!("witcher: request failed", res);
+
+        // Guards and defaults
+        let res = match_err!(chain &err, {
+            x: io::Error if x.kind() == io::ErrorKind::PermissionDenied => "denied",
+            _x: TestError1 => "test1",
+            _ => "other"
+        });
+        assert_eq!("other", res);
+    }
+
+    #[test]
+    fn test_match_err_category() {
+        initialize();
+        #[derive(Clone, Copy, PartialEq)]
+        enum Category {
+            Network,
+            Disk,
+        }
+        let route = |err: &Error| {
+            match_err!(err, {
+                category(Category::Network) => "network",
+                _x: io::Error => "io",
+                _ => "other"
+            })
+        };
+        assert_eq!("network", route(&Error::raw("timeout").with_kind(Category::Network)));
+        assert_eq!("other", route(&Error::raw("full").with_kind(Category::Disk)));
+        assert_eq!("other", route(&Error::raw("oh no!")));
+    }
 }
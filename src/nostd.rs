@@ -0,0 +1,191 @@
+use crate::{Result, StdError};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::fmt::{self, Debug, Display, Formatter};
+
+static ERROR_TYPE: &str = "witcher::Error";
+static STDERROR_TYPE: &str = "std::error::Error";
+static LONG_ERROR_TYPE: &str = "witcher::nostd::Error";
+
+/// `Error` is a wrapper providing additional context and chaining of errors.
+///
+/// This is the minimal `no_std` + `alloc` core used when the `std` feature is disabled. It keeps
+/// the message, type name and chaining of inner errors but without backtraces, colors or any of
+/// the environment driven configuration which all depend on `std`.
+///
+/// ### Examples
+/// ```
+/// #![no_std]
+/// extern crate alloc;
+/// # // Only linked to provide the allocator and panic runtime for running the example
+/// # extern crate std;
+/// use alloc::string::ToString;
+/// use witcher::{err, Error};
+///
+/// fn main() {
+///     let err = Error::wrapr(err!("cause {}", 1), "wrapped");
+///     assert_eq!("wrapped", err.to_string());
+///     assert_eq!(" error: wrapped\n cause: cause 1", alloc::format!("{:#}", err));
+/// }
+/// ```
+pub struct Error {
+    // Error message providing context for the inner error if any
+    msg: String,
+
+    // Type name here will refer to the inner error in the case where
+    // inner error is Some and is an external type else it will be `Error`.
+    type_name: String,
+
+    // Inner error being wrapped with additional context
+    inner: Option<Box<dyn StdError+Send+Sync+'static>>,
+}
+
+impl Error {
+    /// Create a new error instance wrapped in a result
+    pub fn new<T>(msg: &str) -> Result<T> {
+        Err(Error::raw(msg))
+    }
+
+    /// Create a new error instance
+    pub fn raw(msg: &str) -> Self {
+        Self { msg: msg.to_string(), type_name: String::from(ERROR_TYPE), inner: None }
+    }
+
+    /// Wrap the given error and include a contextual message for the error.
+    pub fn wrapr<E>(err: E, msg: &str) -> Self
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        Self { msg: msg.to_string(), type_name: Error::name(&err), inner: Some(Box::new(err)) }
+    }
+
+    /// Wrap the given error and include a contextual message for the error.
+    pub fn wrap<T, E>(err: E, msg: &str) -> Result<T>
+    where
+        E: StdError+Send+Sync+'static,
+    {
+        Err(Error::wrapr(err, msg))
+    }
+
+    /// Iterate over this error and each of its sources in turn
+    pub fn chain(&self) -> impl Iterator<Item = &(dyn StdError+'static)> {
+        core::iter::successors(Some(self as &(dyn StdError+'static)), |&x| x.source())
+    }
+
+    /// Return the last of the error chain for downcasting.
+    /// This will follow the chain of source errors down to the last and return it.
+    /// If this error is the only error it will be returned instead.
+    pub fn last(&self) -> &(dyn StdError+'static) {
+        self.chain().last().unwrap_or(self)
+    }
+
+    /// Implemented directly on the `Error` type to reduce casting required
+    pub fn is<T: StdError+'static>(&self) -> bool {
+        <dyn StdError+'static>::is::<T>(self)
+    }
+
+    /// Implemented directly on the `Error` type to reduce casting required
+    pub fn downcast_ref<T: StdError+'static>(&self) -> Option<&T> {
+        <dyn StdError+'static>::downcast_ref::<T>(self)
+    }
+
+    // Extract the name of the given error type and perform some clean up on the type
+    fn name<T>(_: T) -> String {
+        let mut name = core::any::type_name::<T>();
+
+        // Strip off prefixes
+        name = name.trim_start_matches('&').trim_start_matches("dyn ");
+
+        // Strip off suffixes
+        name = name.split('<').next().unwrap_or("<unknown>");
+
+        // Hide full Error path
+        if name == LONG_ERROR_TYPE {
+            name = ERROR_TYPE;
+        }
+
+        String::from(name)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError+'static)> {
+        match &self.inner {
+            Some(x) => Some(&**x),
+            None => None,
+        }
+    }
+}
+
+/// Writes out each level of the chain with its type name
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut type_name = ERROR_TYPE;
+        for (i, stderr) in self.chain().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match stderr.downcast_ref::<Error>() {
+                Some(err) => {
+                    write!(f, "{}: {}: {}", if i == 0 { " error" } else { " cause" }, ERROR_TYPE, err.msg)?;
+                    type_name = &err.type_name;
+                },
+                _ => {
+                    write!(f, " cause: {}: {}", type_name, stderr)?;
+                    type_name = STDERROR_TYPE;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes out the message or with the alternate flag the message of each level of the chain
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "{}", self.msg);
+        }
+        write!(f, " error: {}", self.msg)?;
+        let mut source = self.source();
+        while let Some(stderr) = source {
+            match stderr.downcast_ref::<Error>() {
+                Some(err) => write!(f, "\n cause: {}", err.msg)?,
+                _ => write!(f, "\n cause: {}", stderr)?,
+            }
+            source = stderr.source();
+        }
+        Ok(())
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[derive(Debug)]
+    struct TestError;
+    impl Display for TestError {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            write!(f, "cause")
+        }
+    }
+    impl StdError for TestError {}
+
+    #[test]
+    fn test_chain() {
+        let err = Error::wrapr(Error::wrapr(TestError, "inner"), "outer");
+        assert_eq!("outer", format!("{}", err));
+        assert_eq!(" error: outer\n cause: inner\n cause: cause", format!("{:#}", err));
+        assert_eq!(" error: witcher::Error: outer\n cause: witcher::Error: inner\n cause: witcher::nostd::tests::TestError: cause", format!("{:?}", err));
+        assert_eq!(3, err.chain().count());
+        assert!(err.last().is::<TestError>());
+        assert!(err.is::<Error>());
+        assert!(err.downcast_ref::<TestError>().is_none());
+    }
+}
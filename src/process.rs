@@ -0,0 +1,48 @@
+use std::{
+    fmt::{self, Formatter},
+    sync::Mutex,
+};
+
+// Command line captured at startup when opted in via `capture_argv`
+static ARGV: Mutex<Option<String>> = Mutex::new(None);
+
+/// Capture the command line used to invoke the process for inclusion in the `Debug` output.
+///
+/// This is opt-in as the arguments may contain sensitive information. Call it once at
+/// startup and the top error's `Debug` output will include a ` command: <argv>` line.
+///
+/// ### Examples
+/// ```
+/// witcher::capture_argv();
+/// ```
+pub fn capture_argv() {
+    let argv: Vec<String> = std::env::args_os().map(|x| x.to_string_lossy().into_owned()).collect();
+    *ARGV.lock().unwrap() = Some(argv.join(" "));
+}
+
+// Get the captured command line if capture was enabled
+pub(crate) fn argv() -> Option<String> {
+    ARGV.lock().ok().and_then(|x| x.clone())
+}
+
+// Write out the captured command line if capture was enabled
+pub(crate) fn write_command(f: &mut Formatter<'_>) -> fmt::Result {
+    match argv() {
+        Some(argv) => writeln!(f, " command: {}", argv),
+        None => Ok(()),
+    }
+}
+
+// Get the hostname of the machine the process is running on
+#[cfg(feature = "process-info")]
+pub(crate) fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0 {
+            let len = buf.iter().position(|x| *x == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).unwrap_or_else(|_| String::from("unknown"))
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// `RetryPolicy` defines how many times to retry and how long to wait between attempts.
+///
+/// ### Examples
+/// ```
+/// use std::time::Duration;
+/// use witcher::prelude::*;
+///
+/// let policy = RetryPolicy::exponential(3, Duration::from_millis(10)).with_cap(Duration::from_millis(25)).with_jitter(Duration::from_millis(5));
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    delay: Duration,
+    exponential: bool,
+    cap: Option<Duration>,
+    jitter: Option<Duration>,
+    sleep: Option<Arc<dyn Fn(Duration)+Send+Sync>>,
+}
+impl RetryPolicy {
+    /// Create a new policy retrying up to `max_attempts` times waiting `delay` between attempts.
+    pub fn fixed(max_attempts: usize, delay: Duration) -> Self {
+        Self { max_attempts, delay, exponential: false, cap: None, jitter: None, sleep: None }
+    }
+
+    /// Create a new policy retrying up to `max_attempts` times doubling the wait between
+    /// attempts starting with the given `base` delay.
+    pub fn exponential(max_attempts: usize, base: Duration) -> Self {
+        Self { max_attempts, delay: base, exponential: true, cap: None, jitter: None, sleep: None }
+    }
+
+    /// Limit the delay between attempts to at most the given `cap` before any jitter is added.
+    pub fn with_cap(mut self, cap: Duration) -> Self {
+        self.cap = Some(cap);
+        self
+    }
+
+    /// Add a random extra wait of up to `jitter` to each delay to spread out retries from
+    /// multiple callers failing at the same time.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = Some(jitter);
+        self
+    }
+
+    /// Use the given function to wait between attempts rather than `std::thread::sleep`.
+    /// This allows for recording the requested delays without actually waiting e.g. in tests.
+    pub fn with_sleep<S>(mut self, sleep: S) -> Self
+    where
+        S: Fn(Duration)+Send+Sync+'static,
+    {
+        self.sleep = Some(Arc::new(sleep));
+        self
+    }
+
+    /// Return the maximum number of retry attempts after the initial failure.
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Return the delay to wait before the given retry attempt starting with `1`.
+    /// The delay is limited by the cap if set but doesn't include any jitter.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let delay = if self.exponential {
+            let exp = attempt.saturating_sub(1).min(u32::MAX as usize) as u32;
+            2u32.checked_pow(exp).and_then(|x| self.delay.checked_mul(x)).unwrap_or(Duration::MAX)
+        } else {
+            self.delay
+        };
+        match self.cap {
+            Some(cap) => delay.min(cap),
+            None => delay,
+        }
+    }
+
+    // Wait before the given retry attempt according to the policy returning the delay used
+    pub(crate) fn wait(&self, attempt: usize) -> Duration {
+        let mut delay = self.delay(attempt);
+        if let Some(jitter) = self.jitter {
+            delay = delay.saturating_add(random_upto(jitter));
+        }
+        match &self.sleep {
+            Some(sleep) => sleep(delay),
+            None if !delay.is_zero() => thread::sleep(delay),
+            None => {},
+        }
+        delay
+    }
+}
+
+// Pick a pseudo random duration between zero and the given max inclusive using the randomly
+// seeded std hasher to avoid pulling in a dependency for random numbers
+fn random_upto(max: Duration) -> Duration {
+    let nanos = max.as_nanos().min(u64::MAX as u128) as u64;
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(nanos);
+    Duration::from_nanos(hasher.finish() % nanos.saturating_add(1))
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::fixed(3, Duration::from_millis(10));
+        assert_eq!(3, policy.max_attempts());
+        assert_eq!(Duration::from_millis(10), policy.delay(1));
+        assert_eq!(Duration::from_millis(10), policy.delay(3));
+
+        let policy = RetryPolicy::exponential(3, Duration::from_millis(100));
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(400), policy.delay(3));
+        assert_eq!(Duration::MAX, policy.delay(100));
+
+        let policy = RetryPolicy::exponential(5, Duration::from_millis(100)).with_cap(Duration::from_millis(300));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(300), policy.delay(3));
+        assert_eq!(Duration::from_millis(300), policy.delay(100));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy::fixed(3, Duration::from_millis(100)).with_jitter(Duration::from_millis(50)).with_sleep(|_| {});
+        for attempt in 1..=100 {
+            let delay = policy.wait(attempt);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(150));
+        }
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::ZERO, random_upto(Duration::ZERO));
+    }
+}
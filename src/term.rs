@@ -0,0 +1,212 @@
+use gory::Color;
+use std::{cell::Cell, sync::RwLock};
+
+thread_local! {
+    // Color override for the current thread which takes precedence over the environment
+    static THREAD_COLOR: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Force color output on or off for the current thread only.
+///
+/// This takes precedence over the environment i.e. `NO_COLOR`, `CLICOLOR_FORCE`, `TERM_COLOR`
+/// and tty detection in that order which are process wide and thus racy when tests run in
+/// parallel. To return to automatic color control simply call with a value of `None`.
+///
+/// ### Examples
+/// ```
+/// witcher::set_thread_color(Some(false));
+/// witcher::set_thread_color(None);
+/// ```
+pub fn set_thread_color(val: Option<bool>) {
+    THREAD_COLOR.with(|x| x.set(val));
+}
+
+// Global theme to color output with, unset means `Theme::default()`
+static THEME: RwLock<Option<Theme>> = RwLock::new(None);
+
+/// `Theme` defines the colors to use for each element of the error output when color is
+/// enabled. An element set to `None` is written out without any color.
+///
+/// ### Examples
+/// ```
+/// use witcher::{Color, Theme};
+/// witcher::set_theme(Theme { message: Some(Color::Yellow), ..Theme::default() });
+/// witcher::set_theme(Theme::default());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Theme {
+    /// Color for the error messages
+    pub message: Option<Color>,
+    /// Color for the error type names
+    pub type_name: Option<Color>,
+    /// Color for the cause messages
+    pub cause: Option<Color>,
+    /// Color for the backtrace frame symbols
+    pub frame: Option<Color>,
+}
+impl Theme {
+    /// Create a theme without any colors at all.
+    pub fn none() -> Self {
+        Self { message: None, type_name: None, cause: None, frame: None }
+    }
+}
+
+// Default theme of red messaging and cyan frames
+impl Default for Theme {
+    fn default() -> Self {
+        Self { message: Some(Color::Red), type_name: Some(Color::Red), cause: Some(Color::Red), frame: Some(Color::Cyan) }
+    }
+}
+
+/// Set the global theme to color error output with when color is enabled.
+///
+/// ### Examples
+/// ```
+/// witcher::set_theme(witcher::Theme::default());
+/// ```
+pub fn set_theme(theme: Theme) {
+    *THEME.write().unwrap() = Some(theme);
+}
+
+/// Get the width of the terminal in columns for sizing output.
+///
+/// With the `terminal_size` feature the width is detected from the terminal. Otherwise or
+/// when detection fails the `COLUMNS` environment variable is used falling back to `80`.
+///
+/// ### Examples
+/// ```
+/// assert!(witcher::term_width() > 0);
+/// ```
+pub fn term_width() -> usize {
+    #[cfg(feature = "terminal_size")]
+    let detected = terminal_size::terminal_size().map(|(w, _)| w.0 as usize);
+    #[cfg(not(feature = "terminal_size"))]
+    let detected = None;
+    width_or_fallback(detected)
+}
+
+// Use the detected width if any falling back on `COLUMNS` then `80`
+fn width_or_fallback(detected: Option<usize>) -> usize {
+    detected.filter(|x| *x > 0).or_else(|| std::env::var("COLUMNS").ok().and_then(|x| x.parse::<usize>().ok()).filter(|x| *x > 0)).unwrap_or(80)
+}
+
+// Check the cross ecosystem color conventions using the given environment lookup.
+// A non-empty `NO_COLOR` disables color, else a `CLICOLOR_FORCE` other than `0` enables it.
+fn env_color<F: Fn(&str) -> Option<String>>(var: F) -> Option<bool> {
+    if var("NO_COLOR").is_some_and(|x| !x.is_empty()) {
+        return Some(false);
+    }
+    match var("CLICOLOR_FORCE") {
+        Some(x) if !x.is_empty() && x != "0" => Some(true),
+        _ => None,
+    }
+}
+
+// Colorized makes the color and theme decision once and then applies it consistently
+pub(crate) struct Colorized {
+    pub colorized: bool,
+    theme: Theme,
+}
+impl Colorized {
+    // Determine if color should be used in order of precedence:
+    // 1. the thread override set with `set_thread_color`
+    // 2. `NO_COLOR` set to a non-empty value disables color
+    // 3. `CLICOLOR_FORCE` set to a value other than `0` enables color even without a tty
+    // 4. `TERM_COLOR` and tty detection
+    pub fn new() -> Self {
+        let colorized = match THREAD_COLOR.with(|x| x.get()) {
+            Some(val) => val,
+            None => env_color(|x| std::env::var(x).ok()).unwrap_or_else(Color::enabled),
+        };
+        Self { colorized, theme: THEME.read().unwrap().unwrap_or_default() }
+    }
+
+    // Never use color e.g. for output destined for something other than a terminal
+    pub fn plain() -> Self {
+        Self { colorized: false, theme: Theme::none() }
+    }
+
+    pub fn message(&self, s: &str) -> String {
+        self.paint(self.theme.message, s)
+    }
+
+    pub fn type_name(&self, s: &str) -> String {
+        self.paint(self.theme.type_name, s)
+    }
+
+    pub fn cause(&self, s: &str) -> String {
+        self.paint(self.theme.cause, s)
+    }
+
+    pub fn frame(&self, s: &str) -> String {
+        self.paint(self.theme.frame, s)
+    }
+
+    // Dimmed rather than bold to de-emphasize e.g. dependency frames
+    pub fn dim_frame(&self, s: &str) -> String {
+        self.style(2, self.theme.frame, s)
+    }
+
+    // Wrap the given string in the ANSI escape codes for the given bold color if enabled
+    fn paint(&self, color: Option<Color>, s: &str) -> String {
+        self.style(1, color, s)
+    }
+
+    // Wrap the given string in the ANSI escape codes for the given style and color if enabled
+    fn style(&self, style: u8, color: Option<Color>, s: &str) -> String {
+        match color {
+            Some(color) if self.colorized => format!("\x1B[{};{}m{}\x1B[0m", style, color, s),
+            _ => s.to_string(),
+        }
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ENV_LOCK;
+
+    #[test]
+    fn test_term_width() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        assert_eq!(120, width_or_fallback(Some(120)));
+        std::env::set_var("COLUMNS", "100");
+        assert_eq!(100, width_or_fallback(None));
+        std::env::set_var("COLUMNS", "foo");
+        assert_eq!(80, width_or_fallback(None));
+        std::env::remove_var("COLUMNS");
+        assert_eq!(80, width_or_fallback(None));
+    }
+
+    #[test]
+    fn test_env_color() {
+        let env = |vars: &'static [(&'static str, &'static str)]| move |key: &str| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string());
+        assert_eq!(None, env_color(env(&[])));
+        assert_eq!(Some(false), env_color(env(&[("NO_COLOR", "1")])));
+        assert_eq!(None, env_color(env(&[("NO_COLOR", "")])));
+        assert_eq!(Some(true), env_color(env(&[("CLICOLOR_FORCE", "1")])));
+        assert_eq!(None, env_color(env(&[("CLICOLOR_FORCE", "0")])));
+        assert_eq!(Some(false), env_color(env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")])));
+    }
+
+    #[test]
+    fn test_thread_color() {
+        std::env::set_var(gory::TERM_COLOR, "0");
+        set_thread_color(Some(true));
+        assert!(Colorized::new().colorized);
+        assert_eq!("\u{1b}[1;91mfoo\u{1b}[0m", Colorized::new().message("foo"));
+        assert_eq!("\u{1b}[1;96mfoo\u{1b}[0m", Colorized::new().frame("foo"));
+        assert_eq!("\u{1b}[2;96mfoo\u{1b}[0m", Colorized::new().dim_frame("foo"));
+
+        // Other threads are unaffected
+        assert!(!std::thread::spawn(|| Colorized::new().colorized).join().unwrap());
+
+        set_thread_color(Some(false));
+        assert!(!Colorized::new().colorized);
+        assert_eq!("foo", Colorized::new().message("foo"));
+        assert_eq!("foo", Colorized::new().dim_frame("foo"));
+        set_thread_color(None);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::{Error, Result, StdError};
-use std::any::TypeId;
+use crate::{Error, Result, RetryPolicy, StdError};
+use std::{
+    any::{Any, TypeId},
+    time::Duration,
+};
 
 /// Define the `wrap` function for Result types
 pub trait Wrapper<T, E> {
@@ -10,26 +13,122 @@ pub trait Wrapper<T, E> {
     /// Wrap the error providing the ability to add more context
     fn wrap(self, msg: &str) -> Result<T>;
 
+    /// Wrap the error taking ownership of the given context message to avoid copying it
+    fn wrap_owned(self, msg: String) -> Result<T>;
+
+    /// Wrap the error with a context message built lazily by the given function.
+    /// The function is only called when there is an error.
+    fn wrap_with<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String;
+
+    /// Wrap the error providing the ability to add more context.
+    /// This is an alias for `wrap` for familiarity when coming from `anyhow`.
+    fn context(self, msg: &str) -> Result<T>;
+
+    /// Wrap the error with a context message built lazily by the given function.
+    /// This is an alias for `wrap_with` for familiarity when coming from `anyhow`.
+    fn with_context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String;
+
+    /// Convert the error to the fallback `Error` returned by the given function.
+    /// The function is only called when there is an error. The original error is attached as
+    /// the cause of the fallback unless the fallback already has a cause of its own in which
+    /// case the original error is dropped.
+    fn or_wrap<F>(self, default: F) -> Result<T>
+    where
+        F: FnOnce() -> Error;
+
+    /// Wrap the error with the given context message and set the exit code to use
+    fn context_code(self, code: i32, msg: &str) -> Result<T>;
+
+    /// Wrap the error with a context message built lazily by the given function and set the
+    /// exit code to use. The function is only called when there is an error.
+    fn wrap_code_with<F, S>(self, code: i32, f: F) -> Result<T>
+    where
+        F: FnOnce() -> S,
+        S: Into<String>;
+
+    /// Wrap the error with a context message built from the error itself.
+    /// The function is only called when there is an error.
+    fn context_from_err<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce(&E) -> String;
+
     /// Check if there is an error and the err is the given error type
     fn err_is<U>(&self) -> bool
     where
         U: StdError+'static;
 
+    /// Return the kind of the first `std::io::Error` in the error chain if there is an error
+    fn io_kind(&self) -> Option<std::io::ErrorKind>;
+
+    /// Return the error if there is one and it is the given error type.
+    ///
+    /// # Panics
+    /// Panics with the given `msg` if the result is `Ok` or if the error is some other type,
+    /// in which case the full alternate `Debug` output of the actual error is included.
+    fn expect_err_is<U>(self, msg: &str) -> E
+    where
+        U: StdError+'static;
+
+    /// Run the given function with a reference to the value if there is one and return the
+    /// result unchanged. This is useful for inline logging or metrics on the happy path.
+    fn tap_ok<F>(self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&T);
+
+    /// Run the given function with a reference to the error if there is one and return the
+    /// result unchanged. This is useful for inline logging or metrics on the failure path.
+    fn tap_err<F>(self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&E);
+
     /// Retry the given function when we have an error `max` number of times.
     fn retry<F>(self, max: usize, f: F) -> Result<T, E>
     where
         F: Fn(usize) -> Result<T, E>;
 
+    /// Retry the given function when we have an error `max` number of times calling the
+    /// `on_retry` hook with the attempt number and the failing error before each retry.
+    fn retry_with_hook<F, H>(self, max: usize, f: F, on_retry: H) -> Result<T, E>
+    where
+        F: Fn(usize) -> Result<T, E>,
+        H: Fn(usize, &E);
+
+    /// Retry the given function when we have an error `max` number of times threading the
+    /// state `S` through each attempt starting with `init`. The function is given the attempt
+    /// number and the current state and returns the new state along with the attempt's result.
+    fn retry_fold<S, F>(self, max: usize, init: S, f: F) -> Result<T, E>
+    where
+        F: FnMut(usize, S) -> (S, Result<T, E>);
+
+    /// Retry the given function when we have an error according to the given policy
+    /// waiting the policy's delay before each retry attempt. When the final error is an
+    /// `Error` the delays waited are recorded on it and available via `attempt_delays`.
+    fn retry_with<F>(self, policy: RetryPolicy, f: F) -> Result<T, E>
+    where
+        F: Fn(usize) -> Result<T, E>;
+
     /// Retry the given function when we have the concreate error `U` `max` number of times.
+    /// When the error is an `Error` the runtime type of the errors in its chain is checked.
     fn retry_on<F>(self, max: usize, id: TypeId, f: F) -> Result<T, E>
     where
         F: Fn(usize) -> Result<T, E>;
+
+    /// Retry the given function while the error is a `std::io::Error` of the given `kind`
+    /// `max` number of times.
+    fn retry_on_kind<F>(self, max: usize, kind: std::io::ErrorKind, f: F) -> Result<T, E>
+    where
+        F: Fn(usize) -> Result<T, E>;
 }
 
 impl<T, E> Wrapper<T, E> for Result<T, E>
 where
     E: StdError+Send+Sync+'static,
 {
+    #[track_caller]
     fn pass(self) -> Result<T> {
         match self {
             Err(err) => Error::pass(err),
@@ -37,6 +136,7 @@ where
         }
     }
 
+    #[track_caller]
     fn wrap(self, msg: &str) -> Result<T> {
         match self {
             Err(err) => Error::wrap(err, msg),
@@ -44,6 +144,88 @@ where
         }
     }
 
+    #[track_caller]
+    fn wrap_owned(self, msg: String) -> Result<T> {
+        match self {
+            Err(err) => Err(Error::wrapr_owned(err, msg)),
+            Ok(val) => Ok(val),
+        }
+    }
+
+    #[track_caller]
+    fn wrap_with<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String,
+    {
+        match self {
+            Err(err) => Err(Error::wrapr_owned(err, f())),
+            Ok(val) => Ok(val),
+        }
+    }
+
+    #[track_caller]
+    fn context(self, msg: &str) -> Result<T> {
+        self.wrap(msg)
+    }
+
+    #[track_caller]
+    fn with_context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String,
+    {
+        self.wrap_with(f)
+    }
+
+    fn or_wrap<F>(self, default: F) -> Result<T>
+    where
+        F: FnOnce() -> Error,
+    {
+        match self {
+            Err(err) => {
+                let fallback = default();
+                match fallback.source() {
+                    Some(_) => Err(fallback),
+                    None => Err(fallback.with_inner(err)),
+                }
+            },
+            Ok(val) => Ok(val),
+        }
+    }
+
+    #[track_caller]
+    fn context_code(self, code: i32, msg: &str) -> Result<T> {
+        match self {
+            Err(err) => Err(Error::wrapr(err, msg).with_code(code)),
+            Ok(val) => Ok(val),
+        }
+    }
+
+    #[track_caller]
+    fn wrap_code_with<F, S>(self, code: i32, f: F) -> Result<T>
+    where
+        F: FnOnce() -> S,
+        S: Into<String>,
+    {
+        match self {
+            Err(err) => Err(Error::wrapr_owned(err, f().into()).with_code(code)),
+            Ok(val) => Ok(val),
+        }
+    }
+
+    #[track_caller]
+    fn context_from_err<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce(&E) -> String,
+    {
+        match self {
+            Err(err) => {
+                let msg = f(&err);
+                Err(Error::wrapr_owned(err, msg))
+            },
+            Ok(val) => Ok(val),
+        }
+    }
+
     fn err_is<U>(&self) -> bool
     where
         U: StdError+'static,
@@ -54,16 +236,110 @@ where
         }
     }
 
+    fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            Ok(_) => None,
+            Err(e) => crate::error::io_kind(e),
+        }
+    }
+
+    #[track_caller]
+    fn expect_err_is<U>(self, msg: &str) -> E
+    where
+        U: StdError+'static,
+    {
+        match self {
+            Ok(_) => panic!("{}: expected an error but got Ok", msg),
+            Err(e) if (&e as &(dyn StdError+'static)).is::<U>() => e,
+            Err(e) => panic!("{}: {:#?}", msg, crate::error::into_error(e)),
+        }
+    }
+
+    fn tap_ok<F>(self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&T),
+    {
+        if let Ok(val) = &self {
+            f(val);
+        }
+        self
+    }
+
+    fn tap_err<F>(self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&E),
+    {
+        if let Err(err) = &self {
+            f(err);
+        }
+        self
+    }
+
     fn retry<F>(self, max: usize, f: F) -> Result<T, E>
     where
         F: Fn(usize) -> Result<T, E>,
+    {
+        self.retry_with(RetryPolicy::fixed(max, Duration::ZERO), f)
+    }
+
+    fn retry_fold<S, F>(self, max: usize, init: S, mut f: F) -> Result<T, E>
+    where
+        F: FnMut(usize, S) -> (S, Result<T, E>),
     {
         let mut retries = 0;
+        let mut state = init;
         let mut result = self;
         while retries < max && result.is_err() {
             retries += 1;
+            let (next, res) = f(retries, state);
+            state = next;
+            result = res;
+        }
+        result
+    }
+
+    fn retry_with_hook<F, H>(self, max: usize, f: F, on_retry: H) -> Result<T, E>
+    where
+        F: Fn(usize) -> Result<T, E>,
+        H: Fn(usize, &E),
+    {
+        let mut retries = 0;
+        let mut result = self;
+        while retries < max {
+            match &result {
+                Err(err) => {
+                    retries += 1;
+                    on_retry(retries, err);
+                    result = f(retries);
+                },
+                Ok(_) => break,
+            }
+        }
+        result
+    }
+
+    fn retry_with<F>(self, policy: RetryPolicy, f: F) -> Result<T, E>
+    where
+        F: Fn(usize) -> Result<T, E>,
+    {
+        let mut retries = 0;
+        let mut delays = Vec::new();
+        let mut result = self;
+        while retries < policy.max_attempts() && result.is_err() {
+            retries += 1;
+            delays.push(policy.wait(retries));
             result = f(retries);
         }
+
+        // Record the delays on the final error when it is an `Error` and there were any
+        if delays.iter().all(|x| x.is_zero()) {
+            return result;
+        }
+        if let Err(err) = &mut result {
+            if let Some(err) = (err as &mut dyn Any).downcast_mut::<Error>() {
+                err.set_attempt_delays(delays);
+            }
+        }
         result
     }
 
@@ -74,9 +350,12 @@ where
         let mut retries = 0;
         let mut result = self;
         while retries < max
-            && match result {
+            && match &result {
                 Ok(_) => false,
-                Err(_) => TypeId::of::<E>() == id,
+                Err(err) => match (err as &dyn Any).downcast_ref::<Error>() {
+                    Some(err) => err.caused_by_any(&[id]),
+                    None => TypeId::of::<E>() == id,
+                },
             }
         {
             retries += 1;
@@ -84,6 +363,81 @@ where
         }
         result
     }
+
+    fn retry_on_kind<F>(self, max: usize, kind: std::io::ErrorKind, f: F) -> Result<T, E>
+    where
+        F: Fn(usize) -> Result<T, E>,
+    {
+        let mut retries = 0;
+        let mut result = self;
+        while retries < max && result.io_kind() == Some(kind) {
+            retries += 1;
+            result = f(retries);
+        }
+        result
+    }
+}
+
+/// Define the `wrap_false` function for Result types with a boolean value
+pub trait BoolWrapper<E> {
+    /// Convert a `false` value into an error with the given message or wrap the error with
+    /// the given message. A `true` value results in `Ok(())`.
+    fn wrap_false(self, msg: &str) -> Result<()>;
+}
+
+impl<E> BoolWrapper<E> for Result<bool, E>
+where
+    E: StdError+Send+Sync+'static,
+{
+    #[track_caller]
+    fn wrap_false(self, msg: &str) -> Result<()> {
+        match self {
+            Err(err) => Error::wrap(err, msg),
+            Ok(false) => Error::new(msg),
+            Ok(true) => Ok(()),
+        }
+    }
+}
+
+/// Define the `flatten_option` function for Result types with an optional value
+pub trait OptionWrapper<T, E> {
+    /// Convert a `None` value into an error with the given message or wrap the error with
+    /// the given message. A `Some(val)` value results in `Ok(val)`.
+    fn flatten_option(self, none_msg: &str) -> Result<T>;
+}
+
+impl<T, E> OptionWrapper<T, E> for Result<Option<T>, E>
+where
+    E: StdError+Send+Sync+'static,
+{
+    #[track_caller]
+    fn flatten_option(self, none_msg: &str) -> Result<T> {
+        match self {
+            Err(err) => Error::wrap(err, none_msg),
+            Ok(None) => Error::new(none_msg),
+            Ok(Some(val)) => Ok(val),
+        }
+    }
+}
+
+/// Define assertions for Result types intended for tests and invariant checks
+pub trait AssertWrapper<T> {
+    /// Return the value or panic with the full alternate `Debug` output of the error.
+    /// This is like `unwrap` but with the rich error rendering.
+    fn assert_ok(self) -> T;
+}
+
+impl<T, E> AssertWrapper<T> for Result<T, E>
+where
+    E: StdError+Send+Sync+'static,
+{
+    #[track_caller]
+    fn assert_ok(self) -> T {
+        match self {
+            Ok(val) => val,
+            Err(err) => panic!("{:#?}", crate::error::into_error(err)),
+        }
+    }
 }
 
 // Unit tests
@@ -91,6 +445,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ENV_LOCK;
 
     use std::sync::Once;
     static INIT: Once = Once::new();
@@ -123,8 +478,14 @@ mod tests {
         do_external_thing().retry_on(3, TypeId::of::<std::io::Error>(), |_| do_external_thing()).wrap("Failed while attacking beast")
     }
 
+    // Location line written out in the alternate display for the given error
+    fn at(err: &Error) -> String {
+        let loc = err.location().unwrap();
+        format!("\n    at: {}:{}", loc.file(), loc.line())
+    }
+
     fn do_external_thing() -> std::io::Result<()> {
-        Err(std::io::Error::new(std::io::ErrorKind::Other, "Oh no, we missed!"))
+        Err(std::io::Error::other("Oh no, we missed!"))
     }
 
     #[test]
@@ -139,6 +500,274 @@ mod tests {
         assert!(err.downcast_ref::<std::io::Error>().is_some());
     }
 
+    #[test]
+    fn test_location() {
+        initialize();
+        let err = do_external_thing().wrap("Failed while attacking beast").unwrap_err();
+        assert_eq!((file!(), line!() - 1), (err.location().unwrap().file(), err.location().unwrap().line()));
+        let err = do_external_thing().pass().unwrap_err();
+        assert_eq!(line!() - 1, err.location().unwrap().line());
+    }
+
+    #[test]
+    fn test_context_code() {
+        initialize();
+        let err = do_external_thing().context_code(2, "Failed while attacking beast").unwrap_err();
+        assert_eq!(Some(2), err.code());
+        assert_eq!(format!(" error: Failed while attacking beast{}\n cause: Oh no, we missed!", at(&err)), format!("{:#}", err));
+        assert!(Ok::<(), std::io::Error>(()).context_code(2, "unused").is_ok());
+    }
+
+    #[test]
+    fn test_context_aliases() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let (err, expected) = (do_external_thing().context("Failed to do thing").unwrap_err(), do_external_thing().wrap("Failed to do thing").unwrap_err());
+        assert_eq!(format!("{:#}", expected), format!("{:#}", err));
+        assert_eq!(format!("{:?}", expected), format!("{:?}", err));
+        assert_eq!(expected.location().unwrap().line(), err.location().unwrap().line());
+
+        let (err, expected) = (do_external_thing().with_context(|| "Failed to do thing".to_string()).unwrap_err(), do_external_thing().wrap_with(|| "Failed to do thing".to_string()).unwrap_err());
+        assert_eq!(format!("{:#}", expected), format!("{:#}", err));
+        assert!(Ok::<(), std::io::Error>(()).with_context(|| unreachable!()).is_ok());
+    }
+
+    #[test]
+    fn test_or_wrap() {
+        initialize();
+        let err = do_external_thing().or_wrap(|| Error::raw("fallback").with_code(3)).unwrap_err();
+        assert_eq!("fallback", err.to_string());
+        assert_eq!(Some(3), err.code());
+        assert_eq!("Oh no, we missed!", err.source().unwrap().to_string());
+        assert!(err.source().unwrap().is::<std::io::Error>());
+
+        // The fallback's own cause is kept
+        let err = do_external_thing().or_wrap(|| Error::wrapr(Error::raw("own cause"), "fallback")).unwrap_err();
+        assert_eq!("own cause", err.source().unwrap().to_string());
+
+        let result = Ok::<i32, std::io::Error>(1).or_wrap(|| unreachable!());
+        assert_eq!(1, result.unwrap());
+    }
+
+    #[test]
+    fn test_wrap_code_with() {
+        initialize();
+        let err = do_external_thing().wrap_code_with(3, || "Failed to do thing").unwrap_err();
+        assert_eq!("Failed to do thing", err.to_string());
+        assert_eq!(Some(3), err.code());
+
+        let result = Ok::<(), std::io::Error>(()).wrap_code_with(3, || -> String { unreachable!() });
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_context_from_err() {
+        initialize();
+        let err = do_external_thing().context_from_err(|e| format!("Failed with {:?}", e.kind())).unwrap_err();
+        assert_eq!("Failed with Other", err.to_string());
+        assert_eq!(format!(" error: Failed with Other{}\n cause: Oh no, we missed!", at(&err)), format!("{:#}", err));
+
+        let result = Ok::<(), std::io::Error>(()).context_from_err(|_| unreachable!());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_wrap_with() {
+        initialize();
+        let err = do_external_thing().wrap_with(|| format!("Failed to do {}", "thing")).unwrap_err();
+        assert_eq!("Failed to do thing", err.to_string());
+        assert_eq!(format!(" error: Failed to do thing{}\n cause: Oh no, we missed!", at(&err)), format!("{:#}", err));
+
+        let result = Ok::<(), std::io::Error>(()).wrap_with(|| unreachable!());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_tap() {
+        initialize();
+        let seen = std::cell::Cell::new(0);
+        assert_eq!(1, Ok::<i32, std::io::Error>(1).tap_ok(|x| seen.set(*x)).unwrap());
+        assert_eq!(1, seen.get());
+        assert!(do_external_thing().tap_ok(|_| seen.set(2)).is_err());
+        assert_eq!(1, seen.get());
+
+        let msg = std::cell::RefCell::new(String::new());
+        assert!(do_external_thing().tap_err(|e| *msg.borrow_mut() = e.to_string()).is_err());
+        assert_eq!("Oh no, we missed!", *msg.borrow());
+        assert!(Ok::<i32, std::io::Error>(1).tap_err(|_| unreachable!()).is_ok());
+    }
+
+    #[test]
+    fn test_io_kind() {
+        initialize();
+        assert_eq!(Some(std::io::ErrorKind::Other), do_external_thing().io_kind());
+        assert_eq!(Some(std::io::ErrorKind::Other), do_external_thing().wrap("wrapped").io_kind());
+        assert_eq!(None, Error::new::<()>("oh no!").io_kind());
+        assert_eq!(None, Ok::<(), std::io::Error>(()).io_kind());
+    }
+
+    #[test]
+    fn test_wrap_false() {
+        initialize();
+        assert!(Ok::<bool, std::io::Error>(true).wrap_false("check failed").is_ok());
+
+        let err = Ok::<bool, std::io::Error>(false).wrap_false("check failed").unwrap_err();
+        assert_eq!(format!(" error: check failed{}", at(&err)), format!("{:#}", err));
+
+        let err = do_external_thing().map(|_| true).wrap_false("check failed").unwrap_err();
+        assert_eq!(format!(" error: check failed{}\n cause: Oh no, we missed!", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_flatten_option() {
+        initialize();
+        assert_eq!(1, Ok::<Option<i32>, std::io::Error>(Some(1)).flatten_option("missing value").unwrap());
+
+        let err = Ok::<Option<i32>, std::io::Error>(None).flatten_option("missing value").unwrap_err();
+        assert_eq!(format!(" error: missing value{}", at(&err)), format!("{:#}", err));
+
+        let err = do_external_thing().map(|_| Some(1)).flatten_option("missing value").unwrap_err();
+        assert_eq!(format!(" error: missing value{}\n cause: Oh no, we missed!", at(&err)), format!("{:#}", err));
+    }
+
+    #[test]
+    fn test_retry_fold() {
+        initialize();
+        let endpoints = ["primary", "secondary", "tertiary"];
+
+        // Rotate through the endpoints until one succeeds
+        let result = Err::<&str, Error>(Error::raw("primary down")).retry_fold(3, 0, |_, i| {
+            let i = i + 1;
+            (i, if endpoints[i] == "tertiary" { Ok(endpoints[i]) } else { Err(Error::raw(&format!("{} down", endpoints[i]))) })
+        });
+        assert_eq!("tertiary", result.unwrap());
+
+        // The final error incorporates the accumulated state
+        let result = do_external_thing().retry_fold(2, Vec::new(), |i, mut tried: Vec<usize>| {
+            tried.push(i);
+            let res = do_external_thing().map_err(|e| std::io::Error::other(format!("{} after attempts {:?}", e, tried)));
+            (tried, res)
+        });
+        assert_eq!("Oh no, we missed! after attempts [1, 2]", result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_retry_with_hook() {
+        initialize();
+        let attempts = std::cell::RefCell::new(Vec::new());
+        let result = do_external_thing().retry_with_hook(3, |_| do_external_thing(), |i, err| attempts.borrow_mut().push((i, err.to_string())));
+        assert!(result.is_err());
+        assert_eq!(vec![1, 2, 3], attempts.borrow().iter().map(|x| x.0).collect::<Vec<usize>>());
+        assert!(attempts.borrow().iter().all(|x| x.1 == "Oh no, we missed!"));
+
+        let count = std::cell::Cell::new(0);
+        let result = do_external_thing().retry_with_hook(3, |i| if i < 2 { do_external_thing() } else { Ok(()) }, |_, _| count.set(count.get() + 1));
+        assert!(result.is_ok());
+        assert_eq!(2, count.get());
+    }
+
+    #[test]
+    fn test_retry_on_kind() {
+        initialize();
+        use std::io::{Error, ErrorKind};
+        let attempts = std::cell::Cell::new(0);
+        let result = Err::<(), Error>(Error::from(ErrorKind::WouldBlock)).retry_on_kind(5, ErrorKind::WouldBlock, |i| {
+            attempts.set(i);
+            match i {
+                1 => Err(Error::from(ErrorKind::WouldBlock)),
+                _ => Err(Error::from(ErrorKind::PermissionDenied)),
+            }
+        });
+        assert_eq!(2, attempts.get());
+        assert_eq!(Some(ErrorKind::PermissionDenied), result.io_kind());
+
+        attempts.set(0);
+        let result = Err::<(), Error>(Error::from(ErrorKind::PermissionDenied)).retry_on_kind(5, ErrorKind::WouldBlock, |i| {
+            attempts.set(i);
+            Ok(())
+        });
+        assert_eq!(0, attempts.get());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_assert_ok() {
+        initialize();
+        assert_eq!(1, Ok::<i32, std::io::Error>(1).assert_ok());
+
+        let panic = std::panic::catch_unwind(|| do_external_thing().wrap("Failed while attacking beast").assert_ok()).unwrap_err();
+        let msg = panic.downcast_ref::<String>().unwrap();
+        let thread = format!("thread: {}\n", std::thread::current().name().unwrap());
+        assert!(msg.starts_with(&format!("{} error: witcher::Error: Failed while attacking beast\n cause: std::io::error::Error: Oh no, we missed!\n", thread)));
+
+        let panic = std::panic::catch_unwind(|| do_external_thing().assert_ok()).unwrap_err();
+        assert!(panic.downcast_ref::<String>().unwrap().starts_with(&format!("{} error: witcher::Error: Oh no, we missed!\n", thread)));
+    }
+
+    #[test]
+    fn test_expect_err_is() {
+        initialize();
+        let err = do_external_thing().expect_err_is::<std::io::Error>("expected an io error");
+        assert_eq!("Oh no, we missed!", err.to_string());
+
+        let err = do_external_thing().wrap("Failed while attacking beast").expect_err_is::<Error>("expected a witcher error");
+        assert_eq!("Failed while attacking beast", err.to_string());
+
+        let panic = std::panic::catch_unwind(|| do_external_thing().expect_err_is::<std::fmt::Error>("expected a fmt error")).unwrap_err();
+        let thread = format!("thread: {}\n", std::thread::current().name().unwrap());
+        assert!(panic.downcast_ref::<String>().unwrap().starts_with(&format!("expected a fmt error: {} error: witcher::Error: Oh no, we missed!\n", thread)));
+    }
+
+    #[test]
+    fn test_retry_with_injected_sleep() {
+        initialize();
+        use std::sync::{Arc, Mutex};
+        let delays = Arc::new(Mutex::new(Vec::new()));
+        let recorder = delays.clone();
+        let policy = RetryPolicy::exponential(3, Duration::from_millis(100)).with_sleep(move |x| recorder.lock().unwrap().push(x));
+
+        let start = std::time::Instant::now();
+        assert!(do_external_thing().retry_with(policy, |_| do_external_thing()).is_err());
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(vec![Duration::from_millis(100), Duration::from_millis(200), Duration::from_millis(400)], *delays.lock().unwrap());
+    }
+
+    #[test]
+    fn test_retry_with_backoff() {
+        initialize();
+        let attempts = std::cell::RefCell::new(Vec::new());
+        let policy = RetryPolicy::exponential(3, Duration::from_millis(5));
+        let start = std::time::Instant::now();
+        let err = do_external_thing()
+            .retry_with(policy, |i| {
+                attempts.borrow_mut().push((i, start.elapsed()));
+                do_external_thing()
+            })
+            .unwrap_err();
+        assert_eq!("Oh no, we missed!", err.to_string());
+
+        // Each attempt waits at least the exponentially growing delay since the last
+        let attempts = attempts.borrow();
+        assert_eq!(vec![1, 2, 3], attempts.iter().map(|x| x.0).collect::<Vec<usize>>());
+        assert!(attempts[0].1 >= Duration::from_millis(5));
+        assert!(attempts[1].1 - attempts[0].1 >= Duration::from_millis(10));
+        assert!(attempts[2].1 - attempts[1].1 >= Duration::from_millis(20));
+
+        // Plain retry doesn't wait or record delays
+        let err = do_external_thing().wrap("Failed while attacking beast").retry(2, |_| do_external_thing().wrap("Failed while attacking beast")).unwrap_err();
+        assert!(err.attempt_delays().is_empty());
+    }
+
+    #[test]
+    fn test_retry_with_attempt_delays() {
+        initialize();
+        let _lock = ENV_LOCK.lock().unwrap();
+        let policy = RetryPolicy::exponential(3, Duration::from_millis(100)).with_sleep(|_| {});
+        let err = do_external_thing().wrap("Failed while attacking beast").retry_with(policy, |_| do_external_thing().wrap("Failed while attacking beast")).unwrap_err();
+        assert_eq!(&[Duration::from_millis(100), Duration::from_millis(200), Duration::from_millis(400)], err.attempt_delays());
+        assert!(format!("{:?}", err).contains(" error: witcher::Error: Failed while attacking beast\ndelays: 100ms, 200ms, 400ms\n"));
+    }
+
     #[test]
     fn test_retry_on() {
         initialize();
@@ -146,4 +775,35 @@ mod tests {
         assert_eq!("Failed while attacking beast", retry_on_concreate_error_type().unwrap_err().to_string());
         assert_eq!("Failed while attacking beast: 3", retry_on_concreate_error_type_using_err_is().unwrap_err().to_string());
     }
+
+    #[test]
+    fn test_retry_on_runtime_type() {
+        initialize();
+        let io = || do_external_thing().wrap("io failed");
+        let fmt = || Err::<(), _>(std::fmt::Error).wrap("fmt failed");
+
+        let attempts = std::cell::Cell::new(0);
+        let result = io().retry_on(3, TypeId::of::<std::io::Error>(), |_| {
+            attempts.set(attempts.get() + 1);
+            io()
+        });
+        assert_eq!("io failed", result.unwrap_err().to_string());
+        assert_eq!(3, attempts.get());
+
+        attempts.set(0);
+        let result = fmt().retry_on(3, TypeId::of::<std::io::Error>(), |_| {
+            attempts.set(attempts.get() + 1);
+            fmt()
+        });
+        assert_eq!("fmt failed", result.unwrap_err().to_string());
+        assert_eq!(0, attempts.get());
+
+        // Retrying stops once the error is no longer the targeted type
+        let result = io().retry_on(3, TypeId::of::<std::io::Error>(), |_| {
+            attempts.set(attempts.get() + 1);
+            fmt()
+        });
+        assert_eq!("fmt failed", result.unwrap_err().to_string());
+        assert_eq!(1, attempts.get());
+    }
 }
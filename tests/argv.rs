@@ -0,0 +1,19 @@
+#![cfg(feature = "std")]
+use witcher::prelude::*;
+
+#[test]
+fn test_capture_argv() {
+    std::env::set_var(gory::TERM_COLOR, "0");
+    let err = Error::raw("oh no!");
+    assert!(!format!("{:?}", err).contains(" command: "));
+
+    witcher::capture_argv();
+    let argv: Vec<String> = std::env::args().collect();
+    let output = format!("{:?}", err);
+    assert!(output.starts_with(&format!(" command: {}\n error: witcher::Error: oh no!", argv.join(" "))));
+
+    // Written out once for a collection of errors rather than for each error
+    let output = format!("{:?}", witcher::Errors::from(vec![Error::raw("first"), Error::raw("second")]));
+    assert!(output.starts_with(&format!(" command: {}\n1.  error: witcher::Error: first", argv.join(" "))));
+    assert_eq!(1, output.matches(" command: ").count());
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "std")]
+use std::sync::Mutex;
+use witcher::prelude::*;
+
+// Serialize tests changing the process wide backtrace variables
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+// Serialized through `ENV_LOCK` as it changes the process wide `RUST_BACKTRACE` variable
+#[test]
+fn test_backtrace_disabled() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    std::env::set_var("RUST_BACKTRACE", "1");
+    assert!(Error::backtrace_enabled());
+    assert!(format!("{:?}", Error::raw("oh no!")).contains("symbol: "));
+
+    std::env::set_var("RUST_BACKTRACE", "0");
+    assert!(!Error::backtrace_enabled());
+    assert!(!format!("{:?}", Error::raw("oh no!")).contains("symbol: "));
+}
+
+// Serialized through `ENV_LOCK` as it changes the process wide backtrace variables
+#[test]
+fn test_witcher_backtrace() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let has_frames = || format!("{:?}", Error::raw("oh no!")).contains("symbol: ");
+
+    // Witcher's variable takes precedence over the standard one
+    std::env::set_var("RUST_BACKTRACE", "0");
+    std::env::set_var(WITCHER_BACKTRACE, "1");
+    assert!(has_frames());
+    std::env::set_var("RUST_BACKTRACE", "1");
+    std::env::set_var(WITCHER_BACKTRACE, "0");
+    assert!(!has_frames());
+
+    // Falls back on the standard one when unset
+    std::env::remove_var(WITCHER_BACKTRACE);
+    assert!(has_frames());
+    std::env::set_var("RUST_BACKTRACE", "0");
+    assert!(!has_frames());
+    std::env::remove_var("RUST_BACKTRACE");
+}
+
+#[test]
+fn test_frames() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    std::env::set_var(WITCHER_BACKTRACE, "1");
+    let err = Error::raw("oh no!");
+    std::env::remove_var(WITCHER_BACKTRACE);
+
+    let frame = err.frames().iter().find(|x| x.symbol().starts_with("backtrace::test_frames")).unwrap();
+    assert!(!frame.is_dependency());
+    assert!(frame.filename().ends_with("tests/backtrace.rs"));
+    assert!(frame.lineno().is_some());
+    assert!(err.frames().iter().any(|x| x.is_dependency()));
+}
+
+#[test]
+fn test_raw_no_bt() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    std::env::set_var(WITCHER_BACKTRACE, "1");
+    let (err, no_bt) = (Error::raw("oh no!"), Error::raw_no_bt("oh no!"));
+    std::env::remove_var(WITCHER_BACKTRACE);
+
+    assert!(!err.frames().is_empty());
+    assert!(no_bt.frames().is_empty());
+    assert!(no_bt.raw_backtrace().is_none());
+    assert!(no_bt.location().is_some());
+    assert!(!format!("{:?}", no_bt).contains("symbol: "));
+}
@@ -0,0 +1,20 @@
+#![cfg(feature = "std")]
+use witcher::prelude::*;
+
+// Runs in its own process as it changes the process wide color variables
+#[test]
+fn test_color_conventions() {
+    std::env::set_var("RUST_BACKTRACE", "0");
+    let err = Error::raw("oh no!");
+    std::env::set_var("NO_COLOR", "1");
+    std::env::set_var("CLICOLOR_FORCE", "1");
+    assert!(!format!("{:#}", err).contains("\x1b["));
+
+    // Forced on even without a tty
+    std::env::remove_var("NO_COLOR");
+    assert!(format!("{:#}", err).starts_with(" error: \x1b[1;91moh no!\x1b[0m"));
+
+    std::env::set_var("CLICOLOR_FORCE", "0");
+    std::env::set_var(gory::TERM_COLOR, "0");
+    assert!(!format!("{:#}", err).contains("\x1b["));
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "log")]
+use std::sync::Mutex;
+use witcher::prelude::*;
+
+// Logger capturing the records for inspection
+struct Capture(Mutex<Vec<(log::Level, String)>>);
+impl log::Log for Capture {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+    }
+    fn flush(&self) {}
+}
+static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+
+// Runs in its own process as it sets the process wide logger
+#[test]
+fn test_log() {
+    std::env::set_var(WITCHER_BACKTRACE, "1");
+    let err = Error::wrapr(Error::wrapr(std::io::Error::other("cause 2"), "cause 1"), "wrapped");
+    std::env::remove_var(WITCHER_BACKTRACE);
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    err.log_error();
+    err.log(log::Level::Warn);
+    err.log(log::Level::Debug);
+    let records = LOGGER.0.lock().unwrap();
+    assert_eq!((log::Level::Error, "wrapped: cause 1: cause 2".to_string()), records[0]);
+    assert_eq!((log::Level::Warn, "wrapped: cause 1: cause 2".to_string()), records[1]);
+
+    // Backtrace only included at debug and trace levels
+    assert_eq!(log::Level::Debug, records[2].0);
+    assert!(records[2].1.starts_with("wrapped: cause 1: cause 2\nsymbol: log::test_log"));
+}
@@ -0,0 +1,24 @@
+#![cfg(feature = "std")]
+use witcher::{prelude::*, Color, Theme};
+
+// Runs in its own process as it changes the process wide theme
+#[test]
+fn test_theme() {
+    std::env::set_var("RUST_BACKTRACE", "0");
+    witcher::set_thread_color(Some(true));
+    let err = Error::wrapr(std::io::Error::other("cause"), "wrapped");
+    let at = format!("\n    at: {}:{}", err.location().unwrap().file(), err.location().unwrap().line());
+    assert_eq!(format!(" error: \x1b[1;91mwrapped\x1b[0m{}\n cause: \x1b[1;91mcause\x1b[0m", at), format!("{:#}", err));
+
+    witcher::set_theme(Theme { message: Some(Color::Yellow), type_name: Some(Color::Blue), cause: Some(Color::Magenta), frame: Some(Color::Green) });
+    assert_eq!(format!(" error: \x1b[1;93mwrapped\x1b[0m{}\n cause: \x1b[1;95mcause\x1b[0m", at), format!("{:#}", err));
+    let output = format!("{:?}", err);
+    assert!(output.contains(" error: \x1b[1;94mwitcher::Error\x1b[0m: \x1b[1;93mwrapped\x1b[0m\n"));
+    assert!(output.contains(" cause: \x1b[1;94mstd::io::error::Error\x1b[0m: \x1b[1;95mcause\x1b[0m\n"));
+
+    witcher::set_theme(Theme::none());
+    assert_eq!(format!(" error: wrapped{}\n cause: cause", at), format!("{:#}", err));
+
+    witcher::set_theme(Theme::default());
+    witcher::set_thread_color(None);
+}